@@ -1,32 +1,241 @@
-use crate::config::AgentConfig;
+use crate::{config::AgentConfig, date, error::LaunError, process, redact::Redactor, signal};
 use anyhow::{Context, Result, bail};
+use log::{debug, info};
+use regex::Regex;
 use std::{
-    fs,
-    path::PathBuf,
+    fmt, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
     process::{Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
 };
 use tempfile::NamedTempFile;
 
 #[derive(Debug, Clone)]
 pub struct AgentRunResult {
     pub stdout: String,
+    pub code: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentError {
+    Interrupted,
+    IdleTimeout(u64),
+}
+
+impl fmt::Display for AgentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Interrupted => write!(f, "agent invocation interrupted"),
+            Self::IdleTimeout(secs) => {
+                write!(f, "agent produced no output for {secs}s and was killed")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AgentError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingAgentCommands {
+    pub missing: Vec<(String, String)>,
+    pub exit_code: i32,
+}
+
+impl fmt::Display for MissingAgentCommands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let listed = self
+            .missing
+            .iter()
+            .map(|(role, command)| format!("{role}.command `{command}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            f,
+            "agent command not found: {listed}. Install it, add it to PATH, or fix the config"
+        )
+    }
+}
+
+impl std::error::Error for MissingAgentCommands {}
+
+pub fn ensure_commands_available(
+    agents: &[(&str, &AgentConfig)],
+    working_dir: &Path,
+    exit_code: i32,
+) -> Result<()> {
+    let missing: Vec<(String, String)> = agents
+        .iter()
+        .filter(|(_, agent)| process::find_program(&agent.command, working_dir).is_none())
+        .map(|(role, agent)| (role.to_string(), agent.command.clone()))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(MissingAgentCommands { missing, exit_code }.into())
+}
+
+pub trait Agent: std::fmt::Debug + Send + Sync {
+    fn invoke(&self, prompt: &str) -> Result<AgentRunResult, LaunError>;
+}
+
+const WARMUP_PROMPT: &str = "This is a connectivity check. Reply with OK and nothing else.";
+
+pub fn warm_up(agent: &dyn Agent, role: &str) -> Result<()> {
+    let started = Instant::now();
+    let result = agent.invoke(WARMUP_PROMPT).with_context(|| {
+        format!("{role} agent warm-up failed; check that its CLI is installed, authenticated, and configured")
+    })?;
+    if result.stdout.trim().is_empty() {
+        bail!(
+            "{role} agent warm-up returned no output; check that its CLI is authenticated and configured"
+        );
+    }
+    info!(
+        "{role} agent is ready ({:.1}s)",
+        started.elapsed().as_secs_f64()
+    );
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
 pub struct CliAgent {
     config: AgentConfig,
+    extra_args: Vec<String>,
+    env: Vec<(String, String)>,
+    working_dir: Option<PathBuf>,
+    kill_grace: Duration,
+    role: String,
+    redactor: Arc<Redactor>,
+    invocations: Arc<AtomicUsize>,
+    failure_patterns: Vec<Regex>,
 }
 
 impl CliAgent {
     pub fn new(config: AgentConfig) -> Self {
-        Self { config }
+        let failure_patterns = config
+            .failure_patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(err) => {
+                    log::warn!("Ignoring invalid failure pattern `{pattern}`: {err}");
+                    None
+                }
+            })
+            .collect();
+        Self {
+            config,
+            extra_args: Vec::new(),
+            env: Vec::new(),
+            working_dir: None,
+            kill_grace: Duration::from_secs(5),
+            role: "agent".to_string(),
+            redactor: Arc::new(Redactor::default()),
+            invocations: Arc::new(AtomicUsize::new(0)),
+            failure_patterns,
+        }
+    }
+
+    pub fn with_extra_args(mut self, extra_args: Vec<String>) -> Self {
+        self.extra_args = extra_args;
+        self
     }
 
-    pub fn invoke(&self, prompt: &str) -> Result<AgentRunResult> {
-        let prompt_file = NamedTempFile::new().context("failed to create temporary prompt file")?;
-        fs::write(prompt_file.path(), prompt).context("failed to write prompt file")?;
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn with_kill_grace(mut self, kill_grace: Duration) -> Self {
+        self.kill_grace = kill_grace;
+        self
+    }
+
+    pub fn with_working_dir(mut self, working_dir: PathBuf) -> Self {
+        self.working_dir = Some(working_dir);
+        self
+    }
+
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.role = role.to_string();
+        self
+    }
+
+    pub fn with_redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    fn stopped(&self, reason: AgentError) -> LaunError {
+        let message = format!("{} agent `{}` was stopped", self.role, self.config.command);
+        match reason {
+            AgentError::Interrupted => LaunError::agent(message),
+            AgentError::IdleTimeout(_) => LaunError::timeout(message),
+        }
+        .with_source(reason)
+    }
+
+    fn write_prompt_file(
+        &self,
+        prompt: &str,
+    ) -> Result<(PathBuf, Option<NamedTempFile>), LaunError> {
+        let dir = match &self.config.prompt_dir {
+            Some(dir) => match &self.working_dir {
+                Some(working_dir) => working_dir.join(dir),
+                None => PathBuf::from(dir),
+            },
+            None => std::env::temp_dir(),
+        };
+        fs::create_dir_all(&dir).map_err(|err| {
+            LaunError::agent(format!(
+                "failed to create prompt directory {}",
+                dir.display()
+            ))
+            .with_source(err)
+        })?;
 
-        let prompt_file_path = normalize_path(prompt_file.path());
+        if self.config.keep_prompt_files {
+            let seq = self.invocations.fetch_add(1, Ordering::Relaxed) + 1;
+            let path = dir.join(format!(
+                "laun-{}-{}-{seq:03}.md",
+                self.role,
+                date::timestamp()
+            ));
+            fs::write(&path, prompt).map_err(|err| {
+                LaunError::agent(format!("failed to write prompt file {}", path.display()))
+                    .with_source(err)
+            })?;
+            debug!("Kept {} prompt at {}", self.role, path.display());
+            return Ok((path, None));
+        }
+
+        let file = NamedTempFile::new_in(&dir).map_err(|err| {
+            LaunError::agent(format!(
+                "failed to create temporary prompt file in {}",
+                dir.display()
+            ))
+            .with_source(err)
+        })?;
+        fs::write(file.path(), prompt)
+            .map_err(|err| LaunError::agent("failed to write prompt file").with_source(err))?;
+        Ok((file.path().to_path_buf(), Some(file)))
+    }
+}
+
+impl Agent for CliAgent {
+    fn invoke(&self, prompt: &str) -> Result<AgentRunResult, LaunError> {
+        if signal::interrupted() {
+            return Err(self.stopped(AgentError::Interrupted));
+        }
+
+        let (prompt_file, _prompt_guard) = self.write_prompt_file(prompt)?;
+        let prompt_file_path = normalize_path(&prompt_file);
         let mut cmd = Command::new(&self.config.command);
         for arg in &self.config.args {
             cmd.arg(replace_template(
@@ -36,34 +245,218 @@ impl CliAgent {
                 &prompt_file_path,
             ));
         }
+        cmd.args(&self.extra_args);
+        cmd.envs(self.env.iter().map(|(key, value)| (key, value)));
+        if let Some(dir) = &self.working_dir {
+            cmd.current_dir(dir);
+        }
         cmd.stdin(Stdio::null())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit());
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        process::isolate_process_group(&mut cmd);
 
-        let output = cmd.output().with_context(|| {
-            format!(
+        let mut child = cmd.spawn().map_err(|err| {
+            LaunError::agent(format!(
                 "failed to run {} for model {}",
                 self.config.command, self.config.model
-            )
+            ))
+            .with_source(err)
         })?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| LaunError::agent("agent stdout was not captured"))?;
+        let stderr = child
+            .stderr
+            .take()
+            .ok_or_else(|| LaunError::agent("agent stderr was not captured"))?;
+        let last_activity = Arc::new(Mutex::new(Instant::now()));
+        let limit = self.config.max_output_bytes;
+        let stdout_forwarder = Arc::new(OutputForwarder::new(
+            Arc::clone(&self.redactor),
+            true,
+            log_stdout,
+        ));
+        let reader = spawn_reader(stdout, Arc::clone(&last_activity), limit, {
+            let forwarder = Arc::clone(&stdout_forwarder);
+            move |chunk| forwarder.forward(chunk)
+        });
+        let forwarder = Arc::new(OutputForwarder::new(
+            Arc::clone(&self.redactor),
+            self.redactor.is_active(),
+            write_stderr,
+        ));
+        let stderr_reader = spawn_reader(stderr, Arc::clone(&last_activity), Some(0), {
+            let forwarder = Arc::clone(&forwarder);
+            move |chunk| forwarder.forward(chunk)
+        });
+        let idle_timeout = self.config.idle_timeout_secs.map(Duration::from_secs);
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            bail!(
-                "agent command failed (status {:?})\nstdout:\n{}\nstderr:\n{}",
-                output.status.code(),
-                stdout.trim(),
-                stderr.trim()
+        let status = loop {
+            if let Some(status) = child
+                .try_wait()
+                .map_err(|err| LaunError::agent("failed to wait for agent").with_source(err))?
+            {
+                break status;
+            }
+            let stop = if signal::interrupted() {
+                Some(AgentError::Interrupted)
+            } else {
+                idle_timeout
+                    .filter(|timeout| {
+                        last_activity
+                            .lock()
+                            .map(|last| last.elapsed() >= *timeout)
+                            .unwrap_or(false)
+                    })
+                    .map(|timeout| AgentError::IdleTimeout(timeout.as_secs()))
+            };
+            if let Some(reason) = stop {
+                process::terminate_group(&mut child, self.kill_grace);
+                let _ = reader.join();
+                let _ = stderr_reader.join();
+                return Err(self.stopped(reason));
+            }
+            thread::sleep(process::POLL_INTERVAL);
+        };
+        let (stdout, total_bytes) = reader.join().unwrap_or_default();
+        let _ = stderr_reader.join();
+        stdout_forwarder.flush();
+        forwarder.flush();
+        let mut stdout = self.redactor.apply(String::from_utf8_lossy(&stdout).trim());
+        if let Some(limit) = limit
+            && total_bytes > limit
+        {
+            log::warn!(
+                "{} output truncated to {limit} of {total_bytes} bytes (agent.max_output_bytes)",
+                self.role
             );
+            stdout.push_str(&format!(
+                "\n[output truncated: kept {limit} of {total_bytes} bytes]"
+            ));
+        }
+
+        if self.config.exit_code_action(status.code()).is_some() {
+            return Ok(AgentRunResult {
+                stdout,
+                code: status.code(),
+            });
+        }
+        if !status.success() {
+            return Err(LaunError::agent(format!(
+                "agent command failed (status {:?})\nstdout:\n{}",
+                status.code(),
+                stdout
+            )));
+        }
+        for regex in &self.failure_patterns {
+            if let Some(found) = regex.find(&stdout) {
+                return Err(LaunError::agent(format!(
+                    "agent output matched failure pattern `{}` at `{}`\nstdout:\n{}",
+                    regex.as_str(),
+                    found.as_str(),
+                    stdout
+                )));
+            }
         }
 
         Ok(AgentRunResult {
-            stdout: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            stdout,
+            code: status.code(),
         })
     }
 }
 
+struct OutputForwarder {
+    redactor: Arc<Redactor>,
+    pending: Mutex<Vec<u8>>,
+    line_buffered: bool,
+    sink: fn(&[u8]),
+}
+
+impl OutputForwarder {
+    fn new(redactor: Arc<Redactor>, line_buffered: bool, sink: fn(&[u8])) -> Self {
+        Self {
+            redactor,
+            pending: Mutex::new(Vec::new()),
+            line_buffered,
+            sink,
+        }
+    }
+
+    fn forward(&self, chunk: &[u8]) {
+        if !self.line_buffered {
+            (self.sink)(chunk);
+            return;
+        }
+        let Ok(mut pending) = self.pending.lock() else {
+            return;
+        };
+        pending.extend_from_slice(chunk);
+        if let Some(end) = pending.iter().rposition(|byte| *byte == b'\n') {
+            let complete: Vec<u8> = pending.drain(..=end).collect();
+            (self.sink)(
+                self.redactor
+                    .apply(&String::from_utf8_lossy(&complete))
+                    .as_bytes(),
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut pending) = self.pending.lock()
+            && !pending.is_empty()
+        {
+            let rest = std::mem::take(&mut *pending);
+            (self.sink)(
+                self.redactor
+                    .apply(&String::from_utf8_lossy(&rest))
+                    .as_bytes(),
+            );
+        }
+    }
+}
+
+fn log_stdout(bytes: &[u8]) {
+    for line in String::from_utf8_lossy(bytes).lines() {
+        info!("{line}");
+    }
+}
+
+fn write_stderr(bytes: &[u8]) {
+    let mut err = io::stderr().lock();
+    let _ = err.write_all(bytes);
+    let _ = err.flush();
+}
+
+fn spawn_reader(
+    mut source: impl Read + Send + 'static,
+    last_activity: Arc<Mutex<Instant>>,
+    limit: Option<usize>,
+    on_chunk: impl Fn(&[u8]) + Send + 'static,
+) -> thread::JoinHandle<(Vec<u8>, usize)> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let mut total = 0;
+        let mut chunk = [0u8; 8192];
+        loop {
+            match source.read(&mut chunk) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if let Ok(mut last) = last_activity.lock() {
+                        *last = Instant::now();
+                    }
+                    on_chunk(&chunk[..n]);
+                    total += n;
+                    let keep = limit.map_or(n, |limit| n.min(limit.saturating_sub(buf.len())));
+                    buf.extend_from_slice(&chunk[..keep]);
+                }
+            }
+        }
+        (buf, total)
+    })
+}
+
 fn replace_template(raw: &str, model: &str, prompt: &str, prompt_file: &str) -> String {
     raw.replace("{model}", model)
         .replace("{prompt}", prompt)
@@ -1,15 +1,28 @@
 use crate::{
-    config::AppConfig,
-    runner::{LoopRunner, RunOptions},
+    config::{AppConfig, WorkflowConfig},
+    date,
+    error::LaunError,
+    logging, metrics,
+    prd::{
+        self, ItemStatus, LintSeverity, MarkOutcome, MatchOptions, PrdDocument, PrdItem, Progress,
+    },
+    runner::{LoopAction, LoopRunner, RunOptions, RunSummary},
+    signal,
+    vfs::RealFs,
+    watch::Watcher,
+    webhook,
 };
 use anyhow::{Context, Result, bail};
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand};
+use log::{debug, error, info};
 use std::{
     fs,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 const DEFAULT_CONFIG: &str = "laun.toml";
+const PROGRESS_BAR_WIDTH: usize = 20;
 
 #[derive(Debug, Parser)]
 #[command(
@@ -18,6 +31,16 @@ const DEFAULT_CONFIG: &str = "laun.toml";
     about = "Dual-agent loop orchestrator for PRD delivery"
 )]
 struct Cli {
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    #[arg(long, global = true)]
+    no_color: bool,
+    #[arg(long, global = true, value_name = "PATH")]
+    project_root: Option<PathBuf>,
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -31,35 +54,378 @@ enum Commands {
         prd: PathBuf,
         #[arg(long)]
         force: bool,
+        #[arg(long, value_name = "HEADING", default_value = "Checklist")]
+        checklist_heading: String,
+        #[arg(long, value_name = "FILE")]
+        from: Option<PathBuf>,
     },
     Run {
         #[arg(long, default_value = DEFAULT_CONFIG)]
         config: PathBuf,
+        #[arg(long, value_name = "PATH")]
+        prd: Option<PathBuf>,
         #[arg(long)]
         max_iterations: Option<usize>,
         #[arg(long)]
         dry_run: bool,
+        #[arg(long, conflicts_with = "dry_run")]
+        dry_run_worker: bool,
+        #[arg(long)]
+        fail_fast: bool,
+        #[arg(long)]
+        continue_on_agent_error: bool,
+        #[arg(long)]
+        interactive: bool,
+        #[arg(long)]
+        step: bool,
+        #[arg(long)]
+        resume: bool,
+        #[arg(long)]
+        retry_item: bool,
+        #[arg(long)]
+        keep_worktree: bool,
+        #[arg(long)]
+        no_mark: bool,
+        #[arg(long)]
+        allow_dirty: bool,
+        #[arg(long)]
+        verbose_git: bool,
+        #[arg(long, value_name = "FILE")]
+        prd_diff: Option<PathBuf>,
+        #[arg(long, value_name = "REGEX")]
+        filter: Option<String>,
+        #[arg(long = "tag", value_name = "NAME")]
+        tags: Vec<String>,
+        #[arg(long, value_name = "HEADING")]
+        section: Option<String>,
+        #[arg(long = "agent-arg", value_name = "ARG", allow_hyphen_values = true)]
+        agent_args: Vec<String>,
+        #[arg(long = "loop-arg", value_name = "ARG", allow_hyphen_values = true)]
+        loop_args: Vec<String>,
+        #[arg(long = "worker-arg", value_name = "ARG", allow_hyphen_values = true)]
+        worker_args: Vec<String>,
+    },
+    Plan {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+        #[arg(long, value_name = "PATH")]
+        prd: Option<PathBuf>,
+        #[arg(long)]
+        max_iterations: Option<usize>,
+        #[arg(long, value_name = "REGEX")]
+        filter: Option<String>,
+        #[arg(long = "tag", value_name = "NAME")]
+        tags: Vec<String>,
+        #[arg(long, value_name = "HEADING")]
+        section: Option<String>,
+        #[arg(long = "agent-arg", value_name = "ARG", allow_hyphen_values = true)]
+        agent_args: Vec<String>,
+        #[arg(long = "loop-arg", value_name = "ARG", allow_hyphen_values = true)]
+        loop_args: Vec<String>,
+    },
+    Next {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+        #[arg(long, value_name = "PATH")]
+        prd: Option<PathBuf>,
+        #[arg(long)]
+        json: bool,
+        #[arg(long, value_name = "REGEX")]
+        filter: Option<String>,
+        #[arg(long = "tag", value_name = "NAME")]
+        tags: Vec<String>,
+        #[arg(long, value_name = "HEADING")]
+        section: Option<String>,
+        #[arg(long = "agent-arg", value_name = "ARG", allow_hyphen_values = true)]
+        agent_args: Vec<String>,
+        #[arg(long = "loop-arg", value_name = "ARG", allow_hyphen_values = true)]
+        loop_args: Vec<String>,
+    },
+    Estimate {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+        #[arg(long, value_name = "PATH")]
+        prd: Option<PathBuf>,
+        #[arg(long)]
+        max_iterations: Option<usize>,
+        #[arg(long, value_name = "REGEX")]
+        filter: Option<String>,
+        #[arg(long = "tag", value_name = "NAME")]
+        tags: Vec<String>,
+        #[arg(long, value_name = "HEADING")]
+        section: Option<String>,
+    },
+    Test {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+        #[arg(long, value_name = "PATH")]
+        prd: Option<PathBuf>,
+        #[arg(long)]
+        all: bool,
     },
     Validate {
         #[arg(long, default_value = DEFAULT_CONFIG)]
         config: PathBuf,
+        #[arg(long)]
+        strict: bool,
+    },
+    Migrate {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+    },
+    LintPrd {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+        #[arg(long)]
+        round_trip: bool,
+    },
+    Archive {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+    },
+    Add {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+        #[arg(required = true, value_name = "TEXT")]
+        text: Vec<String>,
+    },
+    Uncheck {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+        #[arg(value_name = "ITEM")]
+        item: String,
+    },
+    Status {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+        #[arg(long)]
+        json: bool,
+    },
+    List {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+        #[arg(long, value_name = "PATH")]
+        prd: Option<PathBuf>,
+        #[arg(long)]
+        all: bool,
+    },
+    Watch {
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: PathBuf,
+        #[arg(long, value_name = "PATH")]
+        prd: Option<PathBuf>,
+        #[arg(long)]
+        max_iterations: Option<usize>,
+        #[arg(long = "path", value_name = "PATH")]
+        paths: Vec<PathBuf>,
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        debounce_ms: u64,
+        #[arg(long)]
+        allow_dirty: bool,
     },
 }
 
 pub fn run() -> Result<()> {
     let cli = Cli::parse();
+    logging::init(cli.verbose, cli.quiet, cli.no_color);
     match cli.command {
-        Commands::Init { config, prd, force } => init(config.as_path(), prd.as_path(), force),
+        Commands::Init {
+            config,
+            prd,
+            force,
+            checklist_heading,
+            from,
+        } => init(
+            config.as_path(),
+            prd.as_path(),
+            force,
+            &checklist_heading,
+            from.as_deref(),
+        ),
         Commands::Run {
             config,
+            prd,
             max_iterations,
             dry_run,
-        } => run_loop(config, max_iterations, dry_run),
-        Commands::Validate { config } => validate(config),
+            dry_run_worker,
+            fail_fast,
+            continue_on_agent_error,
+            interactive,
+            step,
+            resume,
+            retry_item,
+            keep_worktree,
+            no_mark,
+            allow_dirty,
+            verbose_git,
+            prd_diff,
+            filter,
+            tags,
+            section,
+            agent_args,
+            loop_args,
+            worker_args,
+        } => {
+            let loop_extra_args = agent_args.iter().chain(&loop_args).cloned().collect();
+            let worker_extra_args = agent_args.iter().chain(&worker_args).cloned().collect();
+            run_loop(
+                config,
+                prd,
+                cli.profile.as_deref(),
+                cli.project_root,
+                RunOptions {
+                    max_iterations_override: max_iterations,
+                    dry_run,
+                    dry_run_worker,
+                    fail_fast,
+                    continue_on_agent_error,
+                    interactive,
+                    step,
+                    resume,
+                    retry_item,
+                    keep_worktree,
+                    no_mark,
+                    allow_dirty,
+                    verbose_git,
+                    prd_diff_file: prd_diff,
+                    filter,
+                    tags,
+                    section,
+                    loop_extra_args,
+                    worker_extra_args,
+                },
+            )
+        }
+        Commands::Plan {
+            config,
+            prd,
+            max_iterations,
+            filter,
+            tags,
+            section,
+            agent_args,
+            loop_args,
+        } => plan(
+            config,
+            prd,
+            cli.profile.as_deref(),
+            cli.project_root,
+            RunOptions {
+                max_iterations_override: max_iterations,
+                filter,
+                tags,
+                section,
+                loop_extra_args: agent_args.into_iter().chain(loop_args).collect(),
+                ..RunOptions::default()
+            },
+        ),
+        Commands::Next {
+            config,
+            prd,
+            json,
+            filter,
+            tags,
+            section,
+            agent_args,
+            loop_args,
+        } => next(
+            config,
+            prd,
+            cli.profile.as_deref(),
+            cli.project_root,
+            json,
+            RunOptions {
+                filter,
+                tags,
+                section,
+                loop_extra_args: agent_args.into_iter().chain(loop_args).collect(),
+                ..RunOptions::default()
+            },
+        ),
+        Commands::Estimate {
+            config,
+            prd,
+            max_iterations,
+            filter,
+            tags,
+            section,
+        } => estimate(
+            config,
+            prd,
+            cli.profile.as_deref(),
+            cli.project_root,
+            RunOptions {
+                max_iterations_override: max_iterations,
+                filter,
+                tags,
+                section,
+                ..RunOptions::default()
+            },
+        ),
+        Commands::Validate { config, strict } => {
+            validate(config, cli.profile.as_deref(), cli.project_root, strict)
+        }
+        Commands::Migrate { config } => migrate(&config),
+        Commands::LintPrd { config, round_trip } => {
+            lint_prd(config, cli.profile.as_deref(), cli.project_root, round_trip)
+        }
+        Commands::Archive { config } => archive(config, cli.profile.as_deref(), cli.project_root),
+        Commands::Add { config, text } => add(
+            config,
+            cli.profile.as_deref(),
+            cli.project_root,
+            &text.join(" "),
+        ),
+        Commands::Uncheck { config, item } => {
+            uncheck(config, cli.profile.as_deref(), cli.project_root, &item)
+        }
+        Commands::Test { config, prd, all } => {
+            test(config, prd, cli.profile.as_deref(), cli.project_root, all)
+        }
+        Commands::Status { config, json } => {
+            status(config, cli.profile.as_deref(), cli.project_root, json)
+        }
+        Commands::List { config, prd, all } => {
+            list(config, prd, cli.profile.as_deref(), cli.project_root, all)
+        }
+        Commands::Watch {
+            config,
+            prd,
+            max_iterations,
+            paths,
+            debounce_ms,
+            allow_dirty,
+        } => watch(
+            config,
+            prd,
+            cli.profile.as_deref(),
+            cli.project_root,
+            &paths,
+            Duration::from_millis(debounce_ms),
+            RunOptions {
+                max_iterations_override: max_iterations,
+                allow_dirty,
+                ..RunOptions::default()
+            },
+        ),
     }
 }
 
-fn init(config_path: &Path, prd_path: &Path, force: bool) -> Result<()> {
+fn init(
+    config_path: &Path,
+    prd_path: &Path,
+    force: bool,
+    checklist_heading: &str,
+    from: Option<&Path>,
+) -> Result<()> {
+    let tasks = from
+        .map(|from| {
+            let raw = fs::read_to_string(from)
+                .with_context(|| format!("failed to read task list {}", from.display()))?;
+            prd::parse_task_list(&raw)
+                .with_context(|| format!("failed to parse task list {}", from.display()))
+        })
+        .transpose()?;
     if config_path.exists() && !force {
         bail!(
             "{} already exists. Re-run with --force to overwrite.",
@@ -80,52 +446,643 @@ fn init(config_path: &Path, prd_path: &Path, force: bool) -> Result<()> {
         }
     }
 
+    let mut imported = tasks.as_ref().map(Vec::len);
     if !prd_path.exists() || force {
-        fs::write(prd_path, default_prd_contents())
-            .with_context(|| format!("failed to write {}", prd_path.display()))?;
+        fs::write(
+            prd_path,
+            default_prd_contents(checklist_heading, tasks.as_deref()),
+        )
+        .with_context(|| format!("failed to write {}", prd_path.display()))?;
+    } else if let Some(tasks) = &tasks {
+        imported = Some(prd::import_items(prd_path, tasks, Some(checklist_heading))?);
     }
 
     let mut cfg = AppConfig::default();
     cfg.prd.file = prd_path_for_config(config_path, prd_path);
+    cfg.prd.checklist_heading = Some(checklist_heading.to_string());
     cfg.write(config_path)?;
 
     println!("Wrote {}", config_path.display());
     println!("Wrote {}", prd_path.display());
+    if let (Some(from), Some(tasks), Some(imported)) = (from, &tasks, imported) {
+        println!(
+            "Imported {imported} item(s) from {} ({} already in the PRD or duplicated)",
+            from.display(),
+            tasks.len() - imported
+        );
+    }
     println!("Next: laun run --config {}", config_path.display());
     Ok(())
 }
 
-fn run_loop(config_path: PathBuf, max_iterations: Option<usize>, dry_run: bool) -> Result<()> {
-    let config = AppConfig::load(config_path.as_path())?;
-    let runner = LoopRunner::new(config, config_path.clone());
-    let summary = runner.run(&RunOptions {
-        max_iterations_override: max_iterations,
-        dry_run,
-    })?;
+fn run_loop(
+    config_path: PathBuf,
+    prd_override: Option<PathBuf>,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    options: RunOptions,
+) -> Result<()> {
+    let (runner, workflow) = load_runner(config_path, prd_override, profile, project_root)?;
+    signal::install_handler()?;
+    let summary = run_once(&runner, &workflow, &options)?;
+    print_summary(&summary);
+    Ok(())
+}
 
+fn run_once(
+    runner: &LoopRunner,
+    workflow: &WorkflowConfig,
+    options: &RunOptions,
+) -> Result<RunSummary> {
+    let outcome = runner.run(options);
+    webhook::notify(workflow, &outcome);
+    let summary = outcome?;
+    metrics::export(workflow, &runner.project_root(), &summary);
+    Ok(summary)
+}
+
+fn watch(
+    config_path: PathBuf,
+    prd_override: Option<PathBuf>,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    extra_paths: &[PathBuf],
+    debounce: Duration,
+    options: RunOptions,
+) -> Result<()> {
+    signal::install_handler()?;
+    let (runner, _) = load_runner(
+        config_path.clone(),
+        prd_override.clone(),
+        profile,
+        project_root.clone(),
+    )?;
+    let root = runner.project_root();
+    let mut paths = vec![runner.prd_path(), config_path.clone()];
+    paths.extend(extra_paths.iter().map(|path| root.join(path)));
+    let mut watcher = Watcher::new(paths);
+    let mut changed = Vec::new();
+    loop {
+        if !changed.is_empty() {
+            info!("Change detected: {}", relative_paths(&root, &changed));
+        }
+        let outcome = load_runner(
+            config_path.clone(),
+            prd_override.clone(),
+            profile,
+            project_root.clone(),
+        )
+        .and_then(|(runner, workflow)| run_once(&runner, &workflow, &options));
+        match outcome {
+            Ok(summary) => print_summary(&summary),
+            Err(err) => error!("Run failed: {err:#}"),
+        }
+        if signal::interrupted() {
+            break;
+        }
+        watcher.reset();
+        info!(
+            "Watching {} for changes (Ctrl-C to stop)",
+            relative_paths(&root, watcher.paths())
+        );
+        match watcher.wait_for_change(debounce) {
+            Some(paths) => changed = paths,
+            None => break,
+        }
+    }
+    println!("Stopped watching.");
+    Ok(())
+}
+
+fn relative_paths(root: &Path, paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| {
+            path.strip_prefix(root)
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_summary(summary: &RunSummary) {
     println!("\nRun complete.");
+    println!("Run ID: {}", summary.run_id);
     println!("Iterations: {}", summary.iterations);
     println!("PRD items marked done: {}", summary.completed_items);
     println!("Commits created: {}", summary.commits);
+    if summary.failed_iterations > 0 {
+        println!("Failed agent iterations: {}", summary.failed_iterations);
+    }
+    if summary.empty_targets > 0 {
+        println!("Decisions without a target item: {}", summary.empty_targets);
+    }
+    if summary.splits > 0 {
+        println!("Items split into subtasks: {}", summary.splits);
+    }
+    if summary.decision_retries > 0 {
+        println!(
+            "Loop decisions re-queried after unparseable answers: {}",
+            summary.decision_retries
+        );
+    }
+    if summary.review_change_requests > 0 {
+        println!(
+            "Changes requested by the reviewer: {}",
+            summary.review_change_requests
+        );
+    }
+    if summary.pinned_iterations > 0 {
+        println!(
+            "Iterations pinned to an item (loop agent not asked): {}",
+            summary.pinned_iterations
+        );
+    }
+    if !summary.unmarked_items.is_empty() {
+        println!(
+            "Items worked but left unchecked (--no-mark): {}",
+            summary.unmarked_items.len()
+        );
+        for item in &summary.unmarked_items {
+            println!("  - {item}");
+        }
+    }
+    if !summary.skipped_items.is_empty() {
+        println!(
+            "Items skipped by the worker: {}",
+            summary.skipped_items.len()
+        );
+        for item in &summary.skipped_items {
+            println!("  - {item}");
+        }
+    }
+    if !summary.flaky_items.is_empty() {
+        println!("Possibly flaky items: {}", summary.flaky_items.len());
+        for item in &summary.flaky_items {
+            println!("  - {item}");
+        }
+    }
+    if !summary.items.is_empty() {
+        println!("Files changed per item:");
+        for report in &summary.items {
+            println!(
+                "  {:<9} {}",
+                report.commit.as_deref().unwrap_or("-"),
+                report.item
+            );
+            if report.files.is_empty() {
+                println!("  {:<9} (no files changed)", "");
+            }
+            for file in &report.files {
+                println!("  {:<9} {file}", "");
+            }
+        }
+    }
+    if let Some(diff) = &summary.prd_diff {
+        println!("PRD changes:");
+        print!("{diff}");
+    }
+    println!("Stopped: {}", summary.stop_reason);
+}
+
+fn plan(
+    config_path: PathBuf,
+    prd_override: Option<PathBuf>,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    options: RunOptions,
+) -> Result<()> {
+    let (runner, _) = load_runner(config_path, prd_override, profile, project_root)?;
+    signal::install_handler()?;
+    let steps = runner.plan(&options)?;
+
+    if steps.is_empty() {
+        println!("\nNothing to plan.");
+        return Ok(());
+    }
+    println!("\nPlan ({} step(s)):", steps.len());
+    for (index, step) in steps.iter().enumerate() {
+        println!("{:>3}. {}", index + 1, step.item);
+        if let Some(rationale) = &step.rationale {
+            println!("     {rationale}");
+        }
+    }
     Ok(())
 }
 
-fn validate(config_path: PathBuf) -> Result<()> {
-    let config = AppConfig::load(config_path.as_path())?;
-    config.validate()?;
+fn next(
+    config_path: PathBuf,
+    prd_override: Option<PathBuf>,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    json: bool,
+    options: RunOptions,
+) -> Result<()> {
+    let (runner, _) = load_runner(config_path, prd_override, profile, project_root)?;
+    signal::install_handler()?;
+    let decision = runner.next_decision(&options)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&decision)?);
+        return Ok(());
+    }
+    match decision.action {
+        LoopAction::Done => println!("Next: stop"),
+        LoopAction::Split => println!(
+            "Next: split `{}` into:",
+            decision.target_item.as_deref().unwrap_or_default()
+        ),
+        LoopAction::Delegate => println!(
+            "Next: delegate `{}`",
+            decision
+                .target_item
+                .as_deref()
+                .unwrap_or("(first open item)")
+        ),
+    }
+    for subtask in &decision.subtasks {
+        println!("  - {subtask}");
+    }
+    if let Some(task) = &decision.worker_prompt {
+        println!("Worker task: {task}");
+    }
+    if let Some(reason) = &decision.reason {
+        println!("Reason: {reason}");
+    }
+    Ok(())
+}
+
+fn estimate(
+    config_path: PathBuf,
+    prd_override: Option<PathBuf>,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    options: RunOptions,
+) -> Result<()> {
+    let (runner, _) = load_runner(config_path, prd_override, profile, project_root)?;
+    let estimate = runner.estimate(&options)?;
+
+    if estimate.items.is_empty() {
+        println!("Nothing to estimate.");
+        return Ok(());
+    }
+    println!(
+        "Estimate for {} item(s), one loop and one worker call each (~4 characters per token):",
+        estimate.items.len()
+    );
+    println!("  {:>8} {:>8}  item", "loop", "worker");
+    for item in &estimate.items {
+        println!(
+            "  {:>8} {:>8}  {}",
+            item.loop_tokens, item.worker_tokens, item.item
+        );
+    }
+    let cost = |cost: Option<f64>| {
+        cost.map_or_else(|| "no pricing".to_string(), |cost| format!("${cost:.2}"))
+    };
+    for (role, agent) in [
+        ("Loop agent", &estimate.loop_agent),
+        ("Worker agent", &estimate.worker_agent),
+    ] {
+        println!(
+            "{role} ({}): {} call(s), ~{} input + ~{} output tokens, {}",
+            agent.model,
+            agent.calls,
+            agent.input_tokens,
+            agent.output_tokens,
+            cost(agent.cost)
+        );
+    }
+    println!(
+        "Total: ~{} tokens, {}",
+        estimate.total_tokens(),
+        cost(estimate.total_cost())
+    );
+    Ok(())
+}
+
+fn test(
+    config_path: PathBuf,
+    prd_override: Option<PathBuf>,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    all: bool,
+) -> Result<()> {
+    let (runner, _) = load_runner(config_path, prd_override, profile, project_root)?;
+    let outcomes = runner.test(all)?;
+    if outcomes.is_empty() {
+        println!("No test commands are configured (workflow.execution_tests or workflow.tests).");
+        return Ok(());
+    }
+    for outcome in &outcomes {
+        if outcome.success {
+            println!("PASS {}", outcome.command);
+            debug!("{}", outcome.output);
+        } else {
+            println!("FAIL {} ({})", outcome.command, outcome.status);
+            for line in outcome.output.lines() {
+                println!("  {line}");
+            }
+        }
+    }
+    let failed = outcomes.iter().filter(|outcome| !outcome.success).count();
+    if failed == 0 {
+        println!("All {} test command(s) passed.", outcomes.len());
+        return Ok(());
+    }
+    let message = format!("{failed} of {} test command(s) failed", outcomes.len());
+    if outcomes.iter().any(|outcome| outcome.timed_out) {
+        bail!(LaunError::timeout(message));
+    }
+    bail!(message)
+}
+
+fn load_runner(
+    config_path: PathBuf,
+    prd_override: Option<PathBuf>,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+) -> Result<(LoopRunner, WorkflowConfig)> {
+    let mut config = AppConfig::load_profile(config_path.as_path(), profile)?;
+    if let Some(prd) = prd_override {
+        let root = project_root
+            .clone()
+            .unwrap_or_else(|| config.project_root(&config_path));
+        config.prd.select(&prd, &root)?;
+    }
+    let workflow = config.workflow.clone();
+    let mut runner = LoopRunner::new(config, config_path);
+    if let Some(project_root) = project_root {
+        runner = runner.with_project_root(project_root);
+    }
+    Ok((runner, workflow))
+}
+
+fn validate(
+    config_path: PathBuf,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    strict: bool,
+) -> Result<()> {
+    let config = AppConfig::load_profile(config_path.as_path(), profile)?;
+    let project_root = project_root.unwrap_or_else(|| config.project_root(&config_path));
+    let repo_root = config.repo_root(&config_path, &project_root);
+    config
+        .validate_paths(&project_root, &repo_root, &RealFs)
+        .map_err(LaunError::invalid_config)?;
+    if strict {
+        config
+            .validate_strict(&repo_root)
+            .map_err(LaunError::invalid_config)?;
+    }
     println!("Config is valid: {}", config_path.display());
     Ok(())
 }
 
-fn default_prd_contents() -> &'static str {
-    r#"# Product Requirements
+fn migrate(config_path: &Path) -> Result<()> {
+    let added = AppConfig::migrate(config_path)?;
+    if added.is_empty() {
+        println!("{} is already up to date.", config_path.display());
+        return Ok(());
+    }
+    println!("Updated {}. Added fields:", config_path.display());
+    for field in &added {
+        println!("  - {field}");
+    }
+    Ok(())
+}
+
+fn lint_prd(
+    config_path: PathBuf,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    round_trip: bool,
+) -> Result<()> {
+    let config = AppConfig::load_profile(config_path.as_path(), profile)?;
+    let project_root = project_root.unwrap_or_else(|| config.project_root(&config_path));
+    let prd_path = project_root.join(&config.prd.file);
+    let contents = fs::read_to_string(&prd_path)
+        .with_context(|| format!("failed to read PRD file {}", prd_path.display()))?;
+
+    let mut issues = prd::lint(&contents);
+    if round_trip {
+        issues.extend(
+            prd::check_round_trip(&contents, config.prd.case_sensitive_match)
+                .with_context(|| format!("invalid PRD file {}", prd_path.display()))?,
+        );
+        issues.sort_by_key(|issue| issue.line);
+    }
+    for issue in &issues {
+        println!(
+            "{}:{}: {}: {}",
+            prd_path.display(),
+            issue.line,
+            issue.severity,
+            issue.message
+        );
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == LintSeverity::Error)
+        .count();
+    if errors > 0 {
+        bail!(
+            "{errors} error(s) and {} warning(s) in {}",
+            issues.len() - errors,
+            prd_path.display()
+        );
+    }
+    if issues.is_empty() {
+        println!("No problems found in {}", prd_path.display());
+    }
+    Ok(())
+}
+
+fn archive(
+    config_path: PathBuf,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+) -> Result<()> {
+    let config = AppConfig::load_profile(config_path.as_path(), profile)?;
+    let project_root = project_root.unwrap_or_else(|| config.project_root(&config_path));
+    let prd_path = project_root.join(&config.prd.file);
+    let archive_path = project_root.join(&config.prd.archive_file);
+
+    let moved = prd::archive_completed(&prd_path, &archive_path, &date::today())?;
+    if moved == 0 {
+        println!("No completed items to archive in {}", prd_path.display());
+    } else {
+        println!(
+            "Archived {moved} completed item(s) from {} to {} (backup: {}.bak)",
+            prd_path.display(),
+            archive_path.display(),
+            prd_path.display()
+        );
+    }
+    Ok(())
+}
+
+fn add(
+    config_path: PathBuf,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    text: &str,
+) -> Result<()> {
+    let config = AppConfig::load_profile(config_path.as_path(), profile)?;
+    let project_root = project_root.unwrap_or_else(|| config.project_root(&config_path));
+    let prd_path = project_root.join(&config.prd.file);
+
+    let line = prd::append_item(&prd_path, text, config.prd.checklist_heading.as_deref())?;
+    println!("Added `{}` to {}:{line}", text.trim(), prd_path.display());
+    Ok(())
+}
+
+fn uncheck(
+    config_path: PathBuf,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    item: &str,
+) -> Result<()> {
+    let config = AppConfig::load_profile(config_path.as_path(), profile)?;
+    let project_root = project_root.unwrap_or_else(|| config.project_root(&config_path));
+    let prd_path = project_root.join(&config.prd.file);
+    let options = MatchOptions {
+        case_sensitive: config.prd.case_sensitive_match,
+        ..MatchOptions::default()
+    };
+
+    match prd::uncheck_item(&prd_path, item, &options)? {
+        MarkOutcome::Marked => println!("Reopened `{item}` in {}", prd_path.display()),
+        MarkOutcome::NotFound => {
+            println!(
+                "No completed item matching `{item}` in {}",
+                prd_path.display()
+            )
+        }
+        MarkOutcome::Ambiguous(lines) => bail!(
+            "`{item}` matches several completed items in {} (lines {}); use more of the item text",
+            prd_path.display(),
+            lines
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+    Ok(())
+}
 
-## Checklist
+fn status(
+    config_path: PathBuf,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    json: bool,
+) -> Result<()> {
+    let config = AppConfig::load_profile(config_path.as_path(), profile)?;
+    let project_root = project_root.unwrap_or_else(|| config.project_root(&config_path));
+    let prd_path = project_root.join(&config.prd.file);
+    let prd = PrdDocument::load(&prd_path)?;
+
+    let progress = prd.progress();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&progress)?);
+        return Ok(());
+    }
+    println!(
+        "{}: {}/{} item(s) done {} {}%",
+        prd_path.display(),
+        progress.done,
+        progress.total,
+        progress.bar(PROGRESS_BAR_WIDTH),
+        progress.percent
+    );
+    for (section, items) in prd.sections() {
+        let section_progress = Progress::of(&items);
+        println!(
+            "\n{} ({}/{})",
+            section.map_or_else(|| "(no heading)".to_string(), |name| format!("## {name}")),
+            section_progress.done,
+            section_progress.total
+        );
+        for item in items {
+            println!(
+                "{}- [{}] {}",
+                "  ".repeat(item.depth + 1),
+                item.status.marker(),
+                item.text
+            );
+        }
+    }
+    Ok(())
+}
+
+fn list(
+    config_path: PathBuf,
+    prd_override: Option<PathBuf>,
+    profile: Option<&str>,
+    project_root: Option<PathBuf>,
+    all: bool,
+) -> Result<()> {
+    let mut config = AppConfig::load_profile(config_path.as_path(), profile)?;
+    let project_root = project_root.unwrap_or_else(|| config.project_root(&config_path));
+    let files: Vec<String> = match prd_override {
+        Some(prd) => {
+            config.prd.select(&prd, &project_root)?;
+            vec![config.prd.file.clone()]
+        }
+        None => config
+            .prd
+            .configured_files()
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+    };
+    for (index, file) in files.iter().enumerate() {
+        let prd_path = project_root.join(file);
+        let prd = PrdDocument::load(&prd_path)?;
+        let items: Vec<&PrdItem> = prd
+            .items
+            .iter()
+            .filter(|item| all || item.status != ItemStatus::Done)
+            .collect();
+        if index > 0 {
+            println!();
+        }
+        println!(
+            "{} ({} {}item(s))",
+            prd_path.display(),
+            items.len(),
+            if all { "" } else { "open " }
+        );
+        for item in items {
+            println!(
+                "{}- [{}] {}",
+                "  ".repeat(item.depth + 1),
+                item.status.marker(),
+                item.text
+            );
+        }
+    }
+    Ok(())
+}
+
+fn default_prd_contents(checklist_heading: &str, tasks: Option<&[String]>) -> String {
+    let Some(tasks) = tasks else {
+        return format!(
+            r#"# Product Requirements
+
+## {checklist_heading}
 - [ ] Define dual-agent responsibilities and handoff contract
 - [ ] Implement the first CLI command surface
 - [ ] Add orchestration loop for delegate -> test -> commit
 - [ ] Add retry path for failing tests
 "#
+        );
+    };
+    let items: String = tasks.iter().map(|task| format!("- [ ] {task}\n")).collect();
+    format!("# Product Requirements\n\n## {checklist_heading}\n{items}")
 }
 
 fn prd_path_for_config(config_path: &Path, prd_path: &Path) -> String {
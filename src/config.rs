@@ -1,6 +1,15 @@
+use crate::{agent, error::LaunError, git::Git, prd, redact::Redactor, template, vfs::FileSystem};
 use anyhow::{Context, Result, bail};
+use log::{debug, warn};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::Path};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+const ENV_PREFIX: &str = "LAUN_";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
@@ -8,12 +17,82 @@ pub struct AppConfig {
     pub workflow: WorkflowConfig,
     pub loop_agent: AgentConfig,
     pub worker_agent: AgentConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewer_agent: Option<AgentConfig>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrdConfig {
     pub file: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
     pub auto_mark_completed: bool,
+    #[serde(default)]
+    pub case_sensitive_match: bool,
+    #[serde(default = "default_archive_file")]
+    pub archive_file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checklist_heading: Option<String>,
+    #[serde(default)]
+    pub reprioritize_on_change: bool,
+    #[serde(default)]
+    pub claim_in_progress: bool,
+    #[serde(default = "default_in_progress_marker")]
+    pub in_progress_marker: char,
+}
+
+impl PrdConfig {
+    pub fn configured_files(&self) -> Vec<&str> {
+        let mut files = vec![self.file.as_str()];
+        for file in &self.files {
+            if !files.contains(&file.as_str()) {
+                files.push(file);
+            }
+        }
+        files
+    }
+
+    pub fn select(&mut self, prd: &Path, project_root: &Path) -> Result<()> {
+        if self.files.is_empty() {
+            let prd = std::path::absolute(prd)
+                .with_context(|| format!("failed to resolve PRD path {}", prd.display()))?;
+            self.file = prd.to_string_lossy().into_owned();
+            return Ok(());
+        }
+        let configured = self.configured_files();
+        let Some(selected) = configured
+            .iter()
+            .find(|file| Path::new(file) == prd || same_path(&project_root.join(file), prd))
+        else {
+            bail!(LaunError::config(format!(
+                "{} is not one of the configured PRD files: {}",
+                prd.display(),
+                configured.join(", ")
+            )));
+        };
+        self.file = selected.to_string();
+        Ok(())
+    }
+}
+
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => matches!(
+            (std::path::absolute(a), std::path::absolute(b)),
+            (Ok(a), Ok(b)) if a == b
+        ),
+    }
+}
+
+fn default_archive_file() -> String {
+    "DONE.md".to_string()
+}
+
+fn default_in_progress_marker() -> char {
+    '-'
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,18 +100,237 @@ pub struct WorkflowConfig {
     pub max_iterations: usize,
     pub max_fix_attempts: usize,
     pub auto_commit: bool,
+    #[serde(default)]
+    pub commit_mode: CommitMode,
     pub execution_tests: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tests: BTreeMap<String, TestPhase>,
+    #[serde(default)]
+    pub require_tests: bool,
+    #[serde(default)]
+    pub commit_trailers: bool,
+    #[serde(default)]
+    pub amend_fixes: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    #[serde(default)]
+    pub webhook_on: WebhookOn,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics_pushgateway: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_root: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo_root: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_on_complete: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_complete_command: Option<String>,
+    #[serde(default)]
+    pub sign_commits: bool,
+    #[serde(default)]
+    pub summarize_context: bool,
+    #[serde(default)]
+    pub include_git_log: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_file: Option<String>,
+    #[serde(default)]
+    pub env_override: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<String>,
+    #[serde(default)]
+    pub abort_on_conflict: bool,
+    #[serde(default)]
+    pub review_before_commit: bool,
+    #[serde(default)]
+    pub missing_git: MissingGitAction,
+    #[serde(default)]
+    pub iteration_delay_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_commands: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub commit_type_map: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_preamble_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_preamble_command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bootstrap_prompt: Option<String>,
+    #[serde(default)]
+    pub loop_item_metadata: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redact_patterns: Vec<String>,
+    #[serde(default)]
+    pub redact_common_secrets: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_paths: Option<Vec<String>>,
+    #[serde(default)]
+    pub out_of_scope_action: OutOfScopeAction,
+    #[serde(default = "default_max_split_depth")]
+    pub max_split_depth: usize,
+    #[serde(default = "default_max_subtasks")]
+    pub max_subtasks: usize,
+    #[serde(default = "default_max_pinned_iterations")]
+    pub max_pinned_iterations: usize,
+    #[serde(default)]
+    pub warmup_agents: bool,
+    #[serde(default)]
+    pub use_worktree: bool,
+    #[serde(default = "default_agent_kill_grace_secs")]
+    pub agent_kill_grace_secs: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_timeout_secs: Option<u64>,
+    #[serde(default = "default_missing_agent_exit_code")]
+    pub missing_agent_exit_code: i32,
+}
+
+fn default_max_split_depth() -> usize {
+    2
+}
+
+fn default_max_subtasks() -> usize {
+    8
+}
+
+fn default_max_pinned_iterations() -> usize {
+    3
+}
+
+fn default_agent_kill_grace_secs() -> u64 {
+    5
+}
+
+fn default_missing_agent_exit_code() -> i32 {
+    3
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookOn {
+    #[default]
+    Always,
+    Failure,
+    Completion,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestPhase {
+    pub commands: Vec<String>,
+    #[serde(default)]
+    pub run_when: RunWhen,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunWhen {
+    #[default]
+    Always,
+    BeforeCompletion,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitMode {
+    #[default]
+    PerItem,
+    AtEnd,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfScopeAction {
+    #[default]
+    Abort,
+    Revert,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingGitAction {
+    #[default]
+    Abort,
+    DisableCommits,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
     pub provider: AgentProvider,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub args: Vec<String>,
     pub model: String,
     pub visible_files: Vec<String>,
     pub visible_tests: Vec<String>,
     pub system_prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_dir: Option<String>,
+    #[serde(default)]
+    pub keep_prompt_files: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failure_patterns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_bytes: Option<usize>,
+    #[serde(default)]
+    pub output_mode: OutputMode,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub exit_codes: BTreeMap<String, ExitCodeAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_prompt_chars: Option<usize>,
+    #[serde(default)]
+    pub prompt_overflow: PromptOverflow,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_cost_per_mtok: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_cost_per_mtok: Option<f64>,
+    #[serde(default)]
+    pub inline_file_contents: bool,
+    #[serde(default = "default_inline_file_max_bytes")]
+    pub inline_file_max_bytes: usize,
+    #[serde(default = "default_inline_total_max_bytes")]
+    pub inline_total_max_bytes: usize,
+    #[serde(default)]
+    pub decision_retries: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalation_model: Option<String>,
+}
+
+fn default_inline_file_max_bytes() -> usize {
+    16 * 1024
+}
+
+fn default_inline_total_max_bytes() -> usize {
+    64 * 1024
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    #[default]
+    Inline,
+    Patch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitCodeAction {
+    Skip,
+    Retry,
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptOverflow {
+    #[default]
+    Fail,
+    Trim,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,14 +341,135 @@ pub enum AgentProvider {
     Custom,
 }
 
+impl AgentProvider {
+    pub fn default_invocation(&self) -> Option<(&'static str, &'static [&'static str])> {
+        match self {
+            Self::Codex => Some(("codex", &["exec", "--model", "{model}", "{prompt}"])),
+            Self::Opencode => Some((
+                "opencode",
+                &["run", "--model", "{model}", "--thinking", "{prompt}"],
+            )),
+            Self::Custom => None,
+        }
+    }
+}
+
+impl AgentConfig {
+    pub fn exit_code_action(&self, code: Option<i32>) -> Option<ExitCodeAction> {
+        self.exit_codes.get(&code?.to_string()).copied()
+    }
+
+    fn apply_provider_defaults(&mut self) {
+        let Some((command, args)) = self.provider.default_invocation() else {
+            return;
+        };
+        if self.command.trim().is_empty() {
+            self.command = command.to_string();
+        }
+        if self.args.is_empty() {
+            self.args = args.iter().map(ToString::to_string).collect();
+        }
+    }
+}
+
 impl AppConfig {
     pub fn load(path: &Path) -> Result<Self> {
+        Self::load_profile(path, None)
+    }
+
+    pub fn load_profile(path: &Path, profile: Option<&str>) -> Result<Self> {
+        Self::read_profile(path, profile).map_err(|err| {
+            LaunError::config("failed to load configuration")
+                .with_source(err)
+                .into()
+        })
+    }
+
+    fn read_profile(path: &Path, profile: Option<&str>) -> Result<Self> {
+        let defaults = default_table()?;
+        let env_overrides = env_overrides(&defaults);
+        let mut value: toml::Table = if !path.exists() && !env_overrides.is_empty() {
+            debug!(
+                "No config file at {}; using defaults and {ENV_PREFIX}* environment variables",
+                path.display()
+            );
+            defaults
+        } else {
+            let raw = fs::read_to_string(path)
+                .with_context(|| format!("failed to read config at {}", path.display()))?;
+            toml::from_str(&raw)
+                .with_context(|| format!("failed to parse TOML from {}", path.display()))?
+        };
+        let profiles = match value.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => bail!("`profiles` in {} must be a table", path.display()),
+            None => toml::Table::new(),
+        };
+        if let Some(name) = profile {
+            let Some(overrides) = profiles.get(name) else {
+                let known: Vec<&str> = profiles.keys().map(String::as_str).collect();
+                bail!(
+                    "unknown profile `{name}` in {} (available: {})",
+                    path.display(),
+                    if known.is_empty() {
+                        "none".to_string()
+                    } else {
+                        known.join(", ")
+                    }
+                );
+            };
+            let toml::Value::Table(overrides) = overrides else {
+                bail!("profile `{name}` in {} must be a table", path.display());
+            };
+            merge_tables(&mut value, overrides.clone());
+        }
+        merge_tables(&mut value, env_overrides);
+        let mut cfg: Self = value.try_into().with_context(|| match profile {
+            Some(name) => format!(
+                "invalid config in {} with profile `{name}` (run `laun migrate` to fill in missing fields)",
+                path.display()
+            ),
+            None => format!(
+                "invalid config in {} (run `laun migrate` to fill in missing fields)",
+                path.display()
+            ),
+        })?;
+        cfg.loop_agent.apply_provider_defaults();
+        cfg.worker_agent.apply_provider_defaults();
+        if let Some(reviewer) = &mut cfg.reviewer_agent {
+            reviewer.apply_provider_defaults();
+        }
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    pub fn migrate(path: &Path) -> Result<Vec<String>> {
         let raw = fs::read_to_string(path)
             .with_context(|| format!("failed to read config at {}", path.display()))?;
-        let cfg: Self = toml::from_str(&raw)
+        let mut value: toml::Table = toml::from_str(&raw)
             .with_context(|| format!("failed to parse TOML from {}", path.display()))?;
+        let profiles = value.remove("profiles");
+        let defaults = default_table()?;
+        let mut added = Vec::new();
+        fill_missing(&mut value, &defaults, "", &mut added);
+        if added.is_empty() {
+            return Ok(added);
+        }
+
+        let cfg: Self = value
+            .try_into()
+            .with_context(|| format!("failed to migrate config at {}", path.display()))?;
         cfg.validate()?;
-        Ok(cfg)
+        let mut output = toml::to_string_pretty(&cfg)?;
+        if let Some(profiles) = profiles {
+            let mut rest = toml::Table::new();
+            rest.insert("profiles".to_string(), profiles);
+            output.push('\n');
+            output.push_str(&toml::to_string_pretty(&rest)?);
+        }
+        fs::write(path, output)
+            .with_context(|| format!("failed to write config to {}", path.display()))?;
+        Ok(added)
     }
 
     pub fn write(&self, path: &Path) -> Result<()> {
@@ -64,28 +483,425 @@ impl AppConfig {
         if self.workflow.max_iterations == 0 {
             bail!("workflow.max_iterations must be > 0");
         }
-        if self.loop_agent.command.trim().is_empty() {
-            bail!("loop_agent.command cannot be empty");
+        if self.workflow.test_timeout_secs == Some(0) {
+            bail!("workflow.test_timeout_secs must be > 0");
+        }
+        for (name, agent) in self.agents() {
+            if agent.command.trim().is_empty() && agent.provider.default_invocation().is_none() {
+                bail!("{name}.command cannot be empty for provider `custom`");
+            }
+            if name != "worker_agent" && agent.output_mode == OutputMode::Patch {
+                bail!("{name}.output_mode must be `inline`; only the worker can return patches");
+            }
+            if name != "loop_agent"
+                && (agent.decision_retries > 0 || agent.escalation_model.is_some())
+            {
+                bail!(
+                    "{name}.decision_retries and {name}.escalation_model are not supported; only the loop agent makes decisions"
+                );
+            }
+        }
+        if self.workflow.review_before_commit && self.reviewer_agent.is_none() {
+            bail!("workflow.review_before_commit requires a [reviewer_agent] section");
+        }
+        if self
+            .loop_agent
+            .escalation_model
+            .as_deref()
+            .is_some_and(|model| model.trim().is_empty())
+        {
+            bail!("loop_agent.escalation_model cannot be empty");
+        }
+        for (name, agent) in self.agents() {
+            if agent.max_prompt_chars == Some(0) {
+                bail!("{name}.max_prompt_chars must be > 0");
+            }
+            for (field, cost) in [
+                ("input_cost_per_mtok", agent.input_cost_per_mtok),
+                ("output_cost_per_mtok", agent.output_cost_per_mtok),
+            ] {
+                if cost.is_some_and(|cost| !cost.is_finite() || cost < 0.0) {
+                    bail!("{name}.{field} must be a non-negative number");
+                }
+            }
+            if let Some(code) = agent
+                .exit_codes
+                .keys()
+                .find(|code| code.parse::<i32>().is_err())
+            {
+                bail!("{name}.exit_codes key `{code}` is not an exit code");
+            }
+        }
+        if !prd::IN_PROGRESS_MARKERS.contains(&self.prd.in_progress_marker) {
+            bail!(
+                "prd.in_progress_marker must be one of {}",
+                prd::IN_PROGRESS_MARKERS
+                    .iter()
+                    .map(|marker| format!("`{marker}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        self.workflow.check_allowed_commands()?;
+        Redactor::new(
+            &self.workflow.redact_patterns,
+            self.workflow.redact_common_secrets,
+        )?;
+        let mut variables = self.variables.clone();
+        variables.insert("run_id".to_string(), String::new());
+        for (name, agent) in self.agents() {
+            template::render(&agent.system_prompt, &variables)
+                .with_context(|| format!("invalid {name}.system_prompt"))?;
+            template::render_all(&agent.visible_files, &variables)
+                .with_context(|| format!("invalid {name}.visible_files"))?;
+            template::render_all(&agent.visible_tests, &variables)
+                .with_context(|| format!("invalid {name}.visible_tests"))?;
+            for pattern in &agent.failure_patterns {
+                Regex::new(pattern).with_context(|| {
+                    format!("invalid {name}.failure_patterns entry `{pattern}`")
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate_strict(&self, repo_root: &Path) -> Result<()> {
+        self.validate()?;
+        if self.workflow.missing_git == MissingGitAction::Abort
+            && (self.workflow.use_worktree || self.workflow.commits_enabled())
+        {
+            Git::new(repo_root).ensure_available()?;
+        }
+        agent::ensure_commands_available(
+            &self.agents(),
+            repo_root,
+            self.workflow.missing_agent_exit_code,
+        )
+    }
+
+    pub fn agents(&self) -> Vec<(&'static str, &AgentConfig)> {
+        let mut agents = vec![
+            ("loop_agent", &self.loop_agent),
+            ("worker_agent", &self.worker_agent),
+        ];
+        if let Some(reviewer) = &self.reviewer_agent {
+            agents.push(("reviewer_agent", reviewer));
+        }
+        agents
+    }
+
+    pub fn project_root(&self, config_path: &Path) -> PathBuf {
+        let config_dir = config_dir(config_path);
+        match &self.workflow.project_root {
+            Some(root) => config_dir.join(root),
+            None => config_dir,
+        }
+    }
+
+    pub fn repo_root(&self, config_path: &Path, project_root: &Path) -> PathBuf {
+        match &self.workflow.repo_root {
+            Some(root) => config_dir(config_path).join(root),
+            None => project_root.to_path_buf(),
+        }
+    }
+
+    pub fn validate_paths(
+        &self,
+        project_root: &Path,
+        repo_root: &Path,
+        fs: &dyn FileSystem,
+    ) -> Result<()> {
+        if !project_root.is_dir() {
+            bail!(
+                "project root {} is not an existing directory",
+                project_root.display()
+            );
+        }
+        if !repo_root.is_dir() {
+            bail!(
+                "workflow.repo_root {} is not an existing directory",
+                repo_root.display()
+            );
+        }
+        for file in self.prd.configured_files() {
+            let prd_path = project_root.join(file);
+            if !fs.is_file(&prd_path) {
+                bail!(
+                    "PRD file {} does not exist. Create it with `laun init --prd {file}` or fix `prd.file`/`prd.files`.",
+                    prd_path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_table() -> Result<toml::Table> {
+    let toml::Value::Table(mut defaults) = toml::Value::try_from(AppConfig::default())? else {
+        bail!("default config did not serialize to a table");
+    };
+    for agent in ["loop_agent", "worker_agent"] {
+        if let Some(toml::Value::Table(agent)) = defaults.get_mut(agent) {
+            agent.remove("command");
+            agent.remove("args");
+        }
+    }
+    Ok(defaults)
+}
+
+fn env_overrides(defaults: &toml::Table) -> toml::Table {
+    let mut overrides = toml::Table::new();
+    for (name, raw) in env::vars_os() {
+        let (Some(name), Some(raw)) = (name.to_str(), raw.to_str()) else {
+            continue;
+        };
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let key = key.to_lowercase();
+        let (section, field) = [
+            ("prd_", "prd"),
+            ("loop_", "loop_agent"),
+            ("worker_", "worker_agent"),
+            ("var_", "variables"),
+        ]
+        .into_iter()
+        .find_map(|(prefix, section)| Some((section, key.strip_prefix(prefix)?)))
+        .unwrap_or(("workflow", key.as_str()));
+        if field.is_empty() {
+            continue;
+        }
+        let default = defaults
+            .get(section)
+            .and_then(toml::Value::as_table)
+            .and_then(|table| table.get(field));
+        let value = match default {
+            _ if section == "variables" => toml::Value::String(raw.to_string()),
+            Some(toml::Value::String(_)) => toml::Value::String(raw.to_string()),
+            _ => toml::from_str::<toml::Table>(&format!("value = {raw}"))
+                .ok()
+                .and_then(|mut table| table.remove("value"))
+                .unwrap_or_else(|| toml::Value::String(raw.to_string())),
+        };
+        if !is_config_field(defaults, section, field, &value) {
+            warn!("Ignoring {name}: `{section}.{field}` is not a config field");
+            continue;
+        }
+        debug!("Config override from {name}: {section}.{field}");
+        if let toml::Value::Table(table) = overrides
+            .entry(section)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        {
+            table.insert(field.to_string(), value);
+        }
+    }
+    overrides
+}
+
+fn is_config_field(
+    defaults: &toml::Table,
+    section: &str,
+    field: &str,
+    value: &toml::Value,
+) -> bool {
+    let mut probe = defaults.clone();
+    let mut table = toml::Table::new();
+    table.insert(field.to_string(), value.clone());
+    let mut single = toml::Table::new();
+    single.insert(section.to_string(), toml::Value::Table(table));
+    merge_tables(&mut probe, single);
+    let path = format!("{section}.{field}");
+    let mut known = true;
+    let _: Result<AppConfig, _> =
+        serde_ignored::deserialize(toml::Value::Table(probe), |ignored| {
+            known &= ignored.to_string() != path;
+        });
+    known
+}
+
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(override_table)) => {
+                merge_tables(base_table, override_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn fill_missing(
+    target: &mut toml::Table,
+    defaults: &toml::Table,
+    prefix: &str,
+    added: &mut Vec<String>,
+) {
+    for (key, default) in defaults {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match (target.get_mut(key), default) {
+            (None, _) => {
+                target.insert(key.clone(), default.clone());
+                added.push(path);
+            }
+            (Some(toml::Value::Table(existing)), toml::Value::Table(default)) => {
+                fill_missing(existing, default, &path, added);
+            }
+            (Some(_), _) => {}
+        }
+    }
+}
+
+impl WorkflowConfig {
+    pub fn commits_enabled(&self) -> bool {
+        self.auto_commit && self.commit_mode != CommitMode::Never
+    }
+
+    pub fn test_commands(&self, completing: bool) -> Vec<String> {
+        let phase_commands = |run_when: RunWhen| {
+            self.tests
+                .values()
+                .filter(move |phase| phase.run_when == run_when)
+                .flat_map(|phase| phase.commands.iter().cloned())
+        };
+        let mut commands = self.execution_tests.clone();
+        commands.extend(phase_commands(RunWhen::Always));
+        if completing {
+            commands.extend(phase_commands(RunWhen::BeforeCompletion));
         }
-        if self.worker_agent.command.trim().is_empty() {
-            bail!("worker_agent.command cannot be empty");
+        commands
+    }
+
+    pub fn all_test_commands(&self) -> Vec<String> {
+        self.test_commands(true)
+    }
+
+    pub fn check_allowed_commands(&self) -> Result<()> {
+        let Some(allowed) = &self.allowed_commands else {
+            return Ok(());
+        };
+        for command in &self.all_test_commands() {
+            if command.contains('`') || command.contains("$(") {
+                bail!(
+                    "test command `{command}` uses command substitution, which is not permitted with workflow.allowed_commands"
+                );
+            }
+            for segment in shell_segments(command) {
+                let program = segment
+                    .split_whitespace()
+                    .find(|token| !token.contains('='))
+                    .unwrap_or_default();
+                let name = Path::new(program)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if !allowed
+                    .iter()
+                    .any(|entry| entry == program || *entry == name)
+                {
+                    bail!(
+                        "test command `{command}` runs `{program}`, which is not in workflow.allowed_commands"
+                    );
+                }
+            }
         }
         Ok(())
     }
 }
 
+fn shell_segments(command: &str) -> Vec<&str> {
+    let bytes = command.as_bytes();
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for (index, byte) in bytes.iter().enumerate() {
+        let is_redirect = *byte == b'&'
+            && (index > 0 && matches!(bytes[index - 1], b'>' | b'<')
+                || bytes.get(index + 1) == Some(&b'>'));
+        if matches!(byte, b';' | b'|' | b'\n') || (*byte == b'&' && !is_redirect) {
+            segments.push(&command[start..index]);
+            start = index + 1;
+        }
+    }
+    segments.push(&command[start..]);
+    segments
+        .into_iter()
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+pub fn config_dir(config_path: &Path) -> PathBuf {
+    match config_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             prd: PrdConfig {
                 file: "PRD.md".to_string(),
+                files: Vec::new(),
                 auto_mark_completed: true,
+                case_sensitive_match: false,
+                archive_file: default_archive_file(),
+                checklist_heading: None,
+                reprioritize_on_change: false,
+                claim_in_progress: false,
+                in_progress_marker: default_in_progress_marker(),
             },
             workflow: WorkflowConfig {
                 max_iterations: 12,
                 max_fix_attempts: 2,
                 auto_commit: true,
+                commit_mode: CommitMode::PerItem,
                 execution_tests: vec!["cargo test".to_string()],
+                tests: BTreeMap::new(),
+                require_tests: false,
+                commit_trailers: false,
+                amend_fixes: false,
+                webhook_url: None,
+                webhook_on: WebhookOn::Always,
+                metrics_file: None,
+                metrics_pushgateway: None,
+                project_root: None,
+                repo_root: None,
+                tag_on_complete: None,
+                on_complete_command: None,
+                sign_commits: false,
+                summarize_context: false,
+                include_git_log: 0,
+                env_file: None,
+                env_override: false,
+                signing_key: None,
+                abort_on_conflict: false,
+                review_before_commit: false,
+                missing_git: MissingGitAction::Abort,
+                iteration_delay_ms: 0,
+                allowed_commands: None,
+                commit_type_map: BTreeMap::new(),
+                prompt_preamble_file: None,
+                prompt_preamble_command: None,
+                bootstrap_prompt: None,
+                loop_item_metadata: false,
+                redact_patterns: Vec::new(),
+                redact_common_secrets: false,
+                allowed_paths: None,
+                out_of_scope_action: OutOfScopeAction::Abort,
+                max_split_depth: default_max_split_depth(),
+                max_subtasks: default_max_subtasks(),
+                max_pinned_iterations: default_max_pinned_iterations(),
+                warmup_agents: false,
+                use_worktree: false,
+                agent_kill_grace_secs: default_agent_kill_grace_secs(),
+                test_timeout_secs: None,
+                missing_agent_exit_code: default_missing_agent_exit_code(),
             },
             loop_agent: AgentConfig {
                 provider: AgentProvider::Opencode,
@@ -102,6 +918,23 @@ impl Default for AppConfig {
                 visible_tests: vec!["cargo test -p laun -- --nocapture".to_string()],
                 system_prompt: "You are a fast loop manager. Keep tasks moving with small scoped worker instructions."
                     .to_string(),
+                prompt_template: None,
+                idle_timeout_secs: None,
+                prompt_dir: None,
+                keep_prompt_files: false,
+                failure_patterns: Vec::new(),
+                max_output_bytes: None,
+                output_mode: OutputMode::Inline,
+                exit_codes: BTreeMap::new(),
+                max_prompt_chars: None,
+                prompt_overflow: PromptOverflow::Fail,
+                input_cost_per_mtok: None,
+                output_cost_per_mtok: None,
+                inline_file_contents: false,
+                inline_file_max_bytes: default_inline_file_max_bytes(),
+                inline_total_max_bytes: default_inline_total_max_bytes(),
+                decision_retries: 0,
+                escalation_model: None,
             },
             worker_agent: AgentConfig {
                 provider: AgentProvider::Opencode,
@@ -118,7 +951,26 @@ impl Default for AppConfig {
                 visible_tests: vec!["cargo test".to_string()],
                 system_prompt: "You are the implementation agent. Apply code changes, run commands, and report concise outcomes."
                     .to_string(),
+                prompt_template: None,
+                idle_timeout_secs: None,
+                prompt_dir: None,
+                keep_prompt_files: false,
+                failure_patterns: Vec::new(),
+                max_output_bytes: None,
+                output_mode: OutputMode::Inline,
+                exit_codes: BTreeMap::new(),
+                max_prompt_chars: None,
+                prompt_overflow: PromptOverflow::Fail,
+                input_cost_per_mtok: None,
+                output_cost_per_mtok: None,
+                inline_file_contents: false,
+                inline_file_max_bytes: default_inline_file_max_bytes(),
+                inline_total_max_bytes: default_inline_total_max_bytes(),
+                decision_retries: 0,
+                escalation_model: None,
             },
+            reviewer_agent: None,
+            variables: BTreeMap::new(),
         }
     }
 }
@@ -0,0 +1,19 @@
+use anyhow::{Context, Result};
+use std::{env, path::Path};
+
+pub fn load(path: &Path, override_existing: bool) -> Result<Vec<(String, String)>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let entries = dotenvy::from_path_iter(path)
+        .with_context(|| format!("failed to read env file {}", path.display()))?;
+    let mut vars = Vec::new();
+    for entry in entries {
+        let (key, value) =
+            entry.with_context(|| format!("failed to parse env file {}", path.display()))?;
+        if override_existing || env::var_os(&key).is_none() {
+            vars.push((key, value));
+        }
+    }
+    Ok(vars)
+}
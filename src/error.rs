@@ -0,0 +1,124 @@
+use crate::{
+    agent::{AgentError, MissingAgentCommands},
+    git::MergeConflict,
+};
+use thiserror::Error;
+
+pub type Source = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum LaunError {
+    #[error("{message}")]
+    Config {
+        message: String,
+        #[source]
+        source: Option<Source>,
+    },
+    #[error("{message}")]
+    Agent {
+        message: String,
+        #[source]
+        source: Option<Source>,
+    },
+    #[error("{message}")]
+    Git {
+        message: String,
+        #[source]
+        source: Option<Source>,
+    },
+    #[error("{message}")]
+    Prd {
+        message: String,
+        #[source]
+        source: Option<Source>,
+    },
+    #[error("{message}")]
+    Timeout {
+        message: String,
+        #[source]
+        source: Option<Source>,
+    },
+}
+
+impl LaunError {
+    pub fn config(message: impl Into<String>) -> Self {
+        Self::Config {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn agent(message: impl Into<String>) -> Self {
+        Self::Agent {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn git(message: impl Into<String>) -> Self {
+        Self::Git {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn prd(message: impl Into<String>) -> Self {
+        Self::Prd {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn timeout(message: impl Into<String>) -> Self {
+        Self::Timeout {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn invalid_config(err: impl Into<Source>) -> Self {
+        Self::config("invalid configuration").with_source(err)
+    }
+
+    pub fn with_source(mut self, err: impl Into<Source>) -> Self {
+        let (Self::Config { source, .. }
+        | Self::Agent { source, .. }
+        | Self::Git { source, .. }
+        | Self::Prd { source, .. }
+        | Self::Timeout { source, .. }) = &mut self;
+        *source = Some(err.into());
+        self
+    }
+
+    pub fn source_as<T: std::error::Error + 'static>(&self) -> Option<&T> {
+        std::error::Error::source(self)?.downcast_ref()
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Config { .. } => 2,
+            Self::Agent { .. } => 4,
+            Self::Git { .. } => 5,
+            Self::Prd { .. } => 6,
+            Self::Timeout { .. } => 7,
+        }
+    }
+}
+
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    err.chain()
+        .find_map(|cause| {
+            if let Some(missing) = cause.downcast_ref::<MissingAgentCommands>() {
+                Some(missing.exit_code)
+            } else if let Some(err) = cause.downcast_ref::<LaunError>() {
+                Some(err.exit_code())
+            } else if let Some(AgentError::IdleTimeout(_)) = cause.downcast_ref::<AgentError>() {
+                Some(LaunError::timeout("").exit_code())
+            } else if cause.downcast_ref::<MergeConflict>().is_some() {
+                Some(LaunError::git("").exit_code())
+            } else {
+                None
+            }
+        })
+        .unwrap_or(1)
+}
@@ -0,0 +1,78 @@
+use crate::config::AgentConfig;
+use serde::Serialize;
+
+const CHARS_PER_TOKEN: usize = 4;
+pub const LOOP_RESPONSE_TOKENS: usize = 200;
+pub const WORKER_RESPONSE_TOKENS: usize = 4_000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Estimate {
+    pub items: Vec<ItemEstimate>,
+    pub loop_agent: AgentEstimate,
+    pub worker_agent: AgentEstimate,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemEstimate {
+    pub item: String,
+    pub loop_tokens: usize,
+    pub worker_tokens: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AgentEstimate {
+    pub model: String,
+    pub calls: usize,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub cost: Option<f64>,
+}
+
+impl AgentEstimate {
+    pub fn new(agent: &AgentConfig) -> Self {
+        Self {
+            model: agent.model.clone(),
+            ..Self::default()
+        }
+    }
+
+    pub fn add_call(&mut self, prompt: &str, response_tokens: usize) -> usize {
+        let input = approx_tokens(prompt);
+        self.calls += 1;
+        self.input_tokens += input;
+        self.output_tokens += response_tokens;
+        input + response_tokens
+    }
+
+    pub fn price(&mut self, agent: &AgentConfig) {
+        self.cost = match (agent.input_cost_per_mtok, agent.output_cost_per_mtok) {
+            (None, None) => None,
+            (input, output) => Some(
+                (self.input_tokens as f64 * input.unwrap_or(0.0)
+                    + self.output_tokens as f64 * output.unwrap_or(0.0))
+                    / 1_000_000.0,
+            ),
+        };
+    }
+
+    pub fn total_tokens(&self) -> usize {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+impl Estimate {
+    pub fn total_tokens(&self) -> usize {
+        self.loop_agent.total_tokens() + self.worker_agent.total_tokens()
+    }
+
+    pub fn total_cost(&self) -> Option<f64> {
+        match (self.loop_agent.cost, self.worker_agent.cost) {
+            (None, None) => None,
+            (loop_cost, worker_cost) => Some(loop_cost.unwrap_or(0.0) + worker_cost.unwrap_or(0.0)),
+        }
+    }
+}
+
+pub fn approx_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
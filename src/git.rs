@@ -0,0 +1,443 @@
+use crate::error::LaunError;
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+type Result<T> = std::result::Result<T, LaunError>;
+
+const VERBOSE_OUTPUT_LINES: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct Git {
+    root: PathBuf,
+    sign_commits: bool,
+    signing_key: Option<String>,
+    verbose: bool,
+    abort_on_conflict: bool,
+    available: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangedPath {
+    pub path: String,
+    pub untracked: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub paths: Vec<String>,
+    pub operation: Option<&'static str>,
+    pub aborted: bool,
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unresolved merge conflicts in {}", self.paths.join(", "))?;
+        match (self.operation, self.aborted) {
+            (Some(operation), true) => write!(
+                f,
+                "; the in-progress {operation} was aborted with `git {operation} --abort`"
+            ),
+            (Some(operation), false) => write!(
+                f,
+                "; a {operation} is in progress. Resolve the conflict markers and `git add` the files, or run `git {operation} --abort`"
+            ),
+            (None, _) => write!(
+                f,
+                ". Resolve the conflict markers and `git add` the files before committing"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MergeConflict {}
+
+#[derive(Debug)]
+pub struct GitOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Git {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            sign_commits: false,
+            signing_key: None,
+            verbose: false,
+            abort_on_conflict: false,
+            available: true,
+        }
+    }
+
+    pub fn without_repository(mut self) -> Self {
+        self.available = false;
+        self
+    }
+
+    pub fn ensure_available(&self) -> Result<()> {
+        let output = Command::new("git")
+            .args(["rev-parse", "--is-inside-work-tree"])
+            .current_dir(&self.root)
+            .output();
+        let reason = match output {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                "git is not installed or not on PATH".to_string()
+            }
+            Err(err) => return Err(LaunError::git("failed to run git").with_source(err)),
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(_) => format!("{} is not inside a git work tree", self.root.display()),
+        };
+        Err(LaunError::git(format!(
+            "{reason}. Run `git init` to let laun commit its work, or set \
+             workflow.missing_git = \"disable_commits\" to run without commits."
+        )))
+    }
+
+    pub fn with_signing(mut self, sign_commits: bool, signing_key: Option<String>) -> Self {
+        self.sign_commits = sign_commits;
+        self.signing_key = signing_key;
+        self
+    }
+
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn with_abort_on_conflict(mut self, abort_on_conflict: bool) -> Self {
+        self.abort_on_conflict = abort_on_conflict;
+        self
+    }
+
+    pub fn run(&self, args: &[&str]) -> Result<GitOutput> {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(&self.root)
+            .output()
+            .map_err(|err| {
+                LaunError::git(format!("failed to run `git {}`", args.join(" "))).with_source(err)
+            })?;
+
+        let output = GitOutput {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout)
+                .trim_end()
+                .to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        };
+        if self.verbose {
+            eprintln!("$ git {}", args.join(" "));
+            let lines: Vec<&str> = output.stdout.lines().chain(output.stderr.lines()).collect();
+            for line in lines.iter().take(VERBOSE_OUTPUT_LINES) {
+                eprintln!("  {line}");
+            }
+            if lines.len() > VERBOSE_OUTPUT_LINES {
+                eprintln!("  ... ({} more lines)", lines.len() - VERBOSE_OUTPUT_LINES);
+            }
+            if !output.success {
+                eprintln!("  (git exited with an error)");
+            }
+        }
+        Ok(output)
+    }
+
+    fn run_checked(&self, args: &[&str]) -> Result<String> {
+        if !self.available {
+            return Ok(String::new());
+        }
+        let output = self.run(args)?;
+        if !output.success {
+            return Err(LaunError::git(format!(
+                "`git {}` failed in {}:\n{}{}",
+                args.join(" "),
+                self.root.display(),
+                output.stdout,
+                output.stderr
+            )));
+        }
+        Ok(output.stdout)
+    }
+
+    pub fn has_uncommitted_changes(&self) -> Result<bool> {
+        let status = self.run_checked(&["status", "--porcelain"])?;
+        Ok(!status.is_empty())
+    }
+
+    pub fn changed_paths(&self) -> Result<Vec<ChangedPath>> {
+        let status = self.run_checked(&["status", "--porcelain", "-z", "--untracked-files=all"])?;
+        let mut entries = status.split('\0').filter(|entry| !entry.is_empty());
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next() {
+            let Some((code, path)) = entry.split_at_checked(3) else {
+                continue;
+            };
+            if code.starts_with(['R', 'C']) {
+                entries.next();
+            }
+            paths.push(ChangedPath {
+                path: path.to_string(),
+                untracked: code.starts_with("??"),
+            });
+        }
+        Ok(paths)
+    }
+
+    pub fn working_diff(&self) -> Result<String> {
+        let mut parts = vec![match self.head_commit() {
+            Some(_) => self.run_checked(&["diff", "HEAD"])?,
+            None => self.run_checked(&["diff"])?,
+        }];
+        for changed in self.changed_paths()? {
+            if changed.untracked {
+                let output = self.run(&["diff", "--no-index", "--", "/dev/null", &changed.path])?;
+                parts.push(output.stdout);
+            }
+        }
+        parts.retain(|part| !part.is_empty());
+        Ok(parts.join("\n"))
+    }
+
+    pub fn unmerged_paths(&self) -> Result<Vec<String>> {
+        let output = self.run_checked(&["diff", "--name-only", "--diff-filter=U"])?;
+        Ok(output.lines().map(str::to_string).collect())
+    }
+
+    pub fn operation_in_progress(&self) -> Option<&'static str> {
+        [
+            ("MERGE_HEAD", "merge"),
+            ("rebase-merge", "rebase"),
+            ("rebase-apply", "rebase"),
+            ("CHERRY_PICK_HEAD", "cherry-pick"),
+            ("REVERT_HEAD", "revert"),
+        ]
+        .into_iter()
+        .find(|(marker, _)| {
+            self.run_checked(&["rev-parse", "--git-path", marker])
+                .is_ok_and(|path| self.root.join(path).exists())
+        })
+        .map(|(_, operation)| operation)
+    }
+
+    pub fn check_conflicts(&self) -> Result<()> {
+        let paths = self.unmerged_paths()?;
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let operation = self.operation_in_progress();
+        let aborted = match operation {
+            Some(operation) if self.abort_on_conflict => {
+                self.run_checked(&[operation, "--abort"])?;
+                true
+            }
+            _ => false,
+        };
+        Err(
+            LaunError::git(format!("cannot commit in {}", self.root.display())).with_source(
+                MergeConflict {
+                    paths,
+                    operation,
+                    aborted,
+                },
+            ),
+        )
+    }
+
+    pub fn revert_paths(&self, paths: &[ChangedPath]) -> Result<()> {
+        for changed in paths {
+            let path = changed.path.as_str();
+            let in_head = self
+                .run(&["cat-file", "-e", &format!("HEAD:{path}")])?
+                .success;
+            if in_head {
+                self.run_checked(&[
+                    "restore",
+                    "--source=HEAD",
+                    "--staged",
+                    "--worktree",
+                    "--",
+                    path,
+                ])?;
+                continue;
+            }
+            if !changed.untracked {
+                self.run_checked(&["rm", "-r", "-f", "--cached", "--quiet", "--", path])?;
+            }
+            let full = self.root.join(path);
+            if full.is_dir() {
+                fs::remove_dir_all(&full)
+            } else {
+                fs::remove_file(&full)
+            }
+            .or_else(|err| {
+                if err.kind() == std::io::ErrorKind::NotFound {
+                    Ok(())
+                } else {
+                    Err(err)
+                }
+            })
+            .map_err(|err| {
+                LaunError::git(format!("failed to remove {}", full.display())).with_source(err)
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn apply_patch(&self, patch: &str) -> Result<()> {
+        let file = tempfile::NamedTempFile::new()
+            .map_err(|err| LaunError::git("failed to create a patch file").with_source(err))?;
+        fs::write(file.path(), patch)
+            .map_err(|err| LaunError::git("failed to write the patch file").with_source(err))?;
+        let path = file.path().to_string_lossy();
+        self.run_checked(&["apply", "--recount", &path])?;
+        Ok(())
+    }
+
+    pub fn head_commit(&self) -> Option<String> {
+        self.run_checked(&["rev-parse", "--short", "HEAD"])
+            .ok()
+            .filter(|hash| !hash.is_empty())
+    }
+
+    pub fn commit_stat(&self, commit: &str, color: bool) -> Result<String> {
+        let color = if color {
+            "--color=always"
+        } else {
+            "--color=never"
+        };
+        self.run_checked(&["show", "--stat", "--format=", color, commit])
+    }
+
+    pub fn recent_commits(&self, limit: usize) -> Result<Vec<String>> {
+        if limit == 0 || self.head_commit().is_none() {
+            return Ok(Vec::new());
+        }
+        let limit = limit.to_string();
+        let output = self.run_checked(&["log", "--oneline", "-n", &limit])?;
+        Ok(output.lines().map(str::to_string).collect())
+    }
+
+    pub fn files_changed_since(&self, base: Option<&str>) -> Result<Vec<String>> {
+        let output = match base {
+            Some(base) => self.run_checked(&["diff", "--name-only", base, "HEAD"])?,
+            None => self.run_checked(&[
+                "diff-tree",
+                "--root",
+                "-r",
+                "--no-commit-id",
+                "--name-only",
+                "HEAD",
+            ])?,
+        };
+        Ok(output.lines().map(str::to_string).collect())
+    }
+
+    pub fn current_branch(&self) -> Result<String> {
+        self.run_checked(&["rev-parse", "--abbrev-ref", "HEAD"])
+    }
+
+    pub fn add_worktree(&self, path: &Path, branch: &str) -> Result<()> {
+        let path = path.to_string_lossy();
+        self.run_checked(&["worktree", "add", "-b", branch, &path, "HEAD"])?;
+        Ok(())
+    }
+
+    pub fn remove_worktree(&self, path: &Path) -> Result<()> {
+        let path = path.to_string_lossy();
+        self.run_checked(&["worktree", "remove", &path])?;
+        Ok(())
+    }
+
+    pub fn delete_branch(&self, branch: &str) -> Result<()> {
+        self.run_checked(&["branch", "-D", branch])?;
+        Ok(())
+    }
+
+    pub fn worktree_fingerprint(&self) -> Option<String> {
+        if !self.available {
+            return None;
+        }
+        let status = self.run_checked(&["status", "--porcelain"]).ok()?;
+        let unstaged = self.run_checked(&["diff"]).ok()?;
+        let staged = self.run_checked(&["diff", "--cached"]).ok()?;
+        Some(format!("{status}\n{unstaged}\n{staged}"))
+    }
+
+    pub fn create_annotated_tag(&self, name: &str, message: &str) -> Result<()> {
+        self.run_checked(&["tag", "-a", name, "-m", message])?;
+        Ok(())
+    }
+
+    pub fn commit_all(&self, message: &str, trailers: &[(&str, String)]) -> Result<String> {
+        let message = append_trailers(message, trailers);
+        self.commit(&["-m", &message])
+    }
+
+    pub fn amend_all(&self) -> Result<String> {
+        self.commit(&["--amend", "--no-edit"])
+    }
+
+    pub fn head_trailer(&self, key: &str) -> Option<String> {
+        let format = format!("--format=%(trailers:key={key},valueonly)");
+        self.run_checked(&["log", "-1", &format])
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn commit(&self, commit_args: &[&str]) -> Result<String> {
+        if !self.available {
+            return Err(LaunError::git(format!(
+                "cannot commit: {} is not a git repository",
+                self.root.display()
+            )));
+        }
+        self.check_conflicts()?;
+        self.run_checked(&["add", "-A"])?;
+        let sign_flag = match &self.signing_key {
+            Some(key) => format!("-S{key}"),
+            None => "-S".to_string(),
+        };
+        let mut args = vec!["commit"];
+        if self.sign_commits {
+            args.push(&sign_flag);
+        }
+        args.extend(commit_args);
+        let output = self.run(&args)?;
+        if !output.success {
+            let details = format!("{}{}", output.stdout, output.stderr);
+            let lower = details.to_lowercase();
+            if self.sign_commits && (lower.contains("gpg") || lower.contains("sign")) {
+                return Err(LaunError::git(format!(
+                    "failed to create a signed commit in {}:\n{}\nCheck that a signing key is \
+                     available (set `workflow.signing_key` or `git config user.signingkey`) and \
+                     that `echo test | gpg --clearsign` works, or set `workflow.sign_commits = false`.",
+                    self.root.display(),
+                    details
+                )));
+            }
+            return Err(LaunError::git(format!(
+                "`git {}` failed in {}:\n{}",
+                args.join(" "),
+                self.root.display(),
+                details
+            )));
+        }
+        self.run_checked(&["rev-parse", "--short", "HEAD"])
+    }
+}
+
+fn append_trailers(message: &str, trailers: &[(&str, String)]) -> String {
+    if trailers.is_empty() {
+        return message.to_string();
+    }
+    let block = trailers
+        .iter()
+        .map(|(key, value)| format!("{key}: {value}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{}\n\n{block}", message.trim_end())
+}
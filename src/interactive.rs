@@ -0,0 +1,90 @@
+use anyhow::{Result, bail};
+use std::io::{self, BufRead, IsTerminal, Write};
+
+pub enum Review {
+    Approve(String),
+    Skip,
+    Abort,
+}
+
+pub enum Pause {
+    Continue,
+    Skip,
+    Abort,
+}
+
+pub fn ensure_terminal(flag: &str) -> Result<()> {
+    if !io::stdin().is_terminal() {
+        bail!("{flag} requires stdin to be a terminal");
+    }
+    Ok(())
+}
+
+pub fn pause(phase: &str, detail: &str) -> Result<Pause> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    eprintln!("\n[step] Next phase: {phase}");
+    if !detail.is_empty() {
+        eprintln!("{detail}");
+    }
+    loop {
+        eprint!("[c]ontinue, [s]kip {phase}, [q]uit? ");
+        io::stderr().flush()?;
+        let Some(answer) = read_line(&mut input)? else {
+            return Ok(Pause::Abort);
+        };
+        match answer.trim().to_lowercase().as_str() {
+            "c" | "continue" | "" => return Ok(Pause::Continue),
+            "s" | "skip" => return Ok(Pause::Skip),
+            "q" | "quit" | "abort" => return Ok(Pause::Abort),
+            other => eprintln!("Unknown choice `{other}`."),
+        }
+    }
+}
+
+pub fn review(target_item: &str, worker_task: &str) -> Result<Review> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut task = worker_task.to_string();
+
+    eprintln!("\nTarget item: {target_item}");
+    eprintln!("Worker prompt:\n{task}");
+    loop {
+        eprint!("\n[a]pprove, [e]dit prompt, [s]kip item, [q]uit? ");
+        io::stderr().flush()?;
+        let Some(answer) = read_line(&mut input)? else {
+            return Ok(Review::Abort);
+        };
+        match answer.trim().to_lowercase().as_str() {
+            "a" | "approve" | "" => return Ok(Review::Approve(task)),
+            "s" | "skip" => return Ok(Review::Skip),
+            "q" | "quit" | "abort" => return Ok(Review::Abort),
+            "e" | "edit" => {
+                eprintln!("Enter the new worker prompt. Finish with an empty line:");
+                let mut lines = Vec::new();
+                while let Some(line) = read_line(&mut input)? {
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    lines.push(line);
+                }
+                if lines.is_empty() {
+                    eprintln!("Empty prompt; keeping the previous one.");
+                } else {
+                    task = lines.join("\n");
+                    eprintln!("Worker prompt:\n{task}");
+                }
+            }
+            other => eprintln!("Unknown choice `{other}`."),
+        }
+    }
+}
+
+fn read_line(input: &mut impl BufRead) -> Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
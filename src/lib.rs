@@ -0,0 +1,32 @@
+pub mod agent;
+pub mod cli;
+pub mod config;
+mod date;
+mod diff;
+mod dotenv;
+pub mod error;
+pub mod estimate;
+mod git;
+mod interactive;
+mod logging;
+mod metrics;
+mod patch;
+pub mod prd;
+mod process;
+mod prompt;
+mod redact;
+pub mod runner;
+mod signal;
+mod state;
+mod template;
+pub mod vfs;
+mod watch;
+mod webhook;
+
+pub use agent::{Agent, AgentError, AgentRunResult, CliAgent};
+pub use config::AppConfig;
+pub use error::LaunError;
+pub use runner::{
+    LoopAction, LoopDecision, LoopRunner, RunOptions, RunSummary, StopReason, parse_loop_decision,
+};
+pub use vfs::{FileSystem, MemoryFs, RealFs};
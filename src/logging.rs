@@ -0,0 +1,51 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+struct StderrLogger;
+
+static LOGGER: StderrLogger = StderrLogger;
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+pub fn color_enabled() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+pub fn init(verbose: u8, quiet: bool, no_color: bool) {
+    let color = !no_color && env::var_os("NO_COLOR").is_none() && io::stderr().is_terminal();
+    COLOR.store(color, Ordering::Relaxed);
+    let level = if quiet {
+        LevelFilter::Warn
+    } else {
+        match verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level() && metadata.target().starts_with("laun")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Info => eprintln!("{}", record.args()),
+            Level::Warn => eprintln!("warning: {}", record.args()),
+            level => eprintln!("{}: {}", level.as_str().to_lowercase(), record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
@@ -1,12 +1,6 @@
-mod agent;
-mod cli;
-mod config;
-mod prd;
-mod runner;
-
 fn main() {
-    if let Err(err) = cli::run() {
+    if let Err(err) = laun::cli::run() {
         eprintln!("error: {err:#}");
-        std::process::exit(1);
+        std::process::exit(laun::error::exit_code(&err));
     }
 }
@@ -0,0 +1,90 @@
+use crate::{config::WorkflowConfig, runner::RunSummary};
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::{fmt::Write as _, fs, path::Path, time::Duration};
+
+pub fn export(workflow: &WorkflowConfig, root: &Path, summary: &RunSummary) {
+    if workflow.metrics_file.is_none() && workflow.metrics_pushgateway.is_none() {
+        return;
+    }
+    let body = render(summary);
+
+    if let Some(file) = &workflow.metrics_file {
+        let path = root.join(file);
+        match write_file(&path, &body) {
+            Ok(()) => debug!("Wrote metrics to {}", path.display()),
+            Err(err) => warn!("Failed to write metrics to {}: {err:#}", path.display()),
+        }
+    }
+    if let Some(url) = &workflow.metrics_pushgateway {
+        match push(url, &body) {
+            Ok(()) => debug!("Pushed metrics to {url}"),
+            Err(err) => warn!("Failed to push metrics to {url}: {err:#}"),
+        }
+    }
+}
+
+fn render(summary: &RunSummary) -> String {
+    let metrics: [(&str, &str, &str, String); 5] = [
+        (
+            "laun_iterations_total",
+            "counter",
+            "Loop iterations run.",
+            summary.iterations.to_string(),
+        ),
+        (
+            "laun_items_completed_total",
+            "counter",
+            "PRD items marked done.",
+            summary.completed_items.to_string(),
+        ),
+        (
+            "laun_commits_total",
+            "counter",
+            "Commits created.",
+            summary.commits.to_string(),
+        ),
+        (
+            "laun_fix_attempts_total",
+            "counter",
+            "Worker fix attempts after failing tests.",
+            summary.fix_attempts.to_string(),
+        ),
+        (
+            "laun_run_duration_seconds",
+            "gauge",
+            "Wall-clock duration of the run.",
+            format!("{:.3}", summary.duration_secs),
+        ),
+    ];
+    let mut out = String::new();
+    for (name, kind, help, value) in metrics {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        let _ = writeln!(out, "{name} {value}");
+    }
+    out
+}
+
+fn write_file(path: &Path, body: &str) -> Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    fs::write(path, body).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn push(url: &str, body: &str) -> Result<()> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(10)))
+        .build()
+        .into();
+    agent
+        .post(url)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .send(body)
+        .context("pushgateway request failed")?;
+    Ok(())
+}
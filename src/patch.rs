@@ -0,0 +1,45 @@
+pub const PATCH_INSTRUCTIONS: &str = "Do not edit files yourself. Reply with every change as a single unified diff \
+(the format `git diff` produces, with paths relative to the repository root) inside a ```diff fenced block. \
+laun applies it with `git apply`; a diff that does not apply cleanly is rejected as a whole.";
+
+pub fn extract(output: &str) -> Option<String> {
+    let fenced = fenced_blocks(output)
+        .into_iter()
+        .filter(|block| looks_like_diff(block))
+        .collect::<Vec<_>>();
+    if !fenced.is_empty() {
+        return Some(finish(&fenced.join("\n")));
+    }
+    let lines: Vec<&str> = output.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| line.starts_with("diff --git ") || line.starts_with("--- "))?;
+    let patch = lines[start..].join("\n");
+    looks_like_diff(&patch).then(|| finish(&patch))
+}
+
+fn fenced_blocks(output: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+    for line in output.lines() {
+        match current.as_mut() {
+            None if line.trim_start().starts_with("```") => current = Some(Vec::new()),
+            None => {}
+            Some(block) if line.trim() == "```" => {
+                blocks.push(block.join("\n"));
+                current = None;
+            }
+            Some(block) => block.push(line),
+        }
+    }
+    blocks
+}
+
+fn looks_like_diff(text: &str) -> bool {
+    let has_line = |prefix: &str| text.lines().any(|line| line.starts_with(prefix));
+    has_line("--- ") && has_line("+++ ") && has_line("@@")
+}
+
+fn finish(patch: &str) -> String {
+    format!("{}\n", patch.trim_end_matches(['\n', '\r']))
+}
@@ -1,93 +1,1743 @@
-use anyhow::{Context, Result};
-use std::{fs, path::Path};
+use crate::{
+    error::LaunError,
+    runner::truncate,
+    vfs::{FileSystem, MemoryFs, RealFs},
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, path::Path};
+
+type Result<T> = std::result::Result<T, LaunError>;
+
+pub const IN_PROGRESS_MARKERS: &[char] = &['-', '/', '>'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemStatus {
+    Todo,
+    InProgress,
+    Done,
+    Deferred,
+}
+
+impl ItemStatus {
+    fn from_marker(marker: char) -> Option<Self> {
+        match marker {
+            ' ' => Some(Self::Todo),
+            '-' | '/' | '>' => Some(Self::InProgress),
+            'x' | 'X' => Some(Self::Done),
+            '~' => Some(Self::Deferred),
+            _ => None,
+        }
+    }
+
+    pub fn is_claimable(self) -> bool {
+        matches!(self, Self::Todo | Self::InProgress)
+    }
+
+    pub fn marker(self) -> char {
+        match self {
+            Self::Todo => ' ',
+            Self::InProgress => '-',
+            Self::Done => 'x',
+            Self::Deferred => '~',
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct PrdItem {
     pub text: String,
-    pub checked: bool,
+    pub normalized: String,
+    pub status: ItemStatus,
+    pub line: usize,
+    pub depth: usize,
+    pub section: Option<String>,
+    pub tags: Vec<String>,
+    pub acceptance: Vec<String>,
+    pub done_when: Option<String>,
+    pub files: Vec<String>,
+    pub priority: Option<String>,
+    pub estimate: Option<String>,
+    pub depends_on: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MatchOptions {
+    pub case_sensitive: bool,
+    pub depth: Option<usize>,
+    pub section: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PrdMetadata {
+    pub max_iterations: Option<usize>,
+    pub max_fix_attempts: Option<usize>,
+    pub execution_tests: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct PrdDocument {
+    pub metadata: PrdMetadata,
     pub items: Vec<PrdItem>,
 }
 
 impl PrdDocument {
     pub fn load(path: &Path) -> Result<Self> {
-        let raw = fs::read_to_string(path)
-            .with_context(|| format!("failed to read PRD file {}", path.display()))?;
-        Ok(Self::parse(&raw))
+        Self::load_from(&RealFs, path)
     }
 
-    pub fn parse(input: &str) -> Self {
-        let mut items = Vec::new();
+    pub fn load_from(fs: &dyn FileSystem, path: &Path) -> Result<Self> {
+        let raw = fs.read_to_string(path).map_err(|err| {
+            LaunError::prd(format!("failed to read PRD file {}", path.display())).with_source(err)
+        })?;
+        Self::parse(&raw).map_err(|err| {
+            LaunError::prd(format!("invalid PRD file {}", path.display())).with_source(err)
+        })
+    }
 
-        for line in input.lines() {
-            let trimmed = line.trim_start();
-            if let Some(text) = trimmed.strip_prefix("- [ ] ") {
-                items.push(PrdItem {
-                    text: text.trim().to_string(),
-                    checked: false,
-                });
-            } else if let Some(text) = trimmed
-                .strip_prefix("- [x] ")
-                .or_else(|| trimmed.strip_prefix("- [X] "))
+    pub fn parse(input: &str) -> Result<Self> {
+        let lines: Vec<&str> = input.lines().collect();
+        let body_start = frontmatter_len(&lines);
+        let metadata = if body_start == 0 {
+            PrdMetadata::default()
+        } else {
+            let yaml = lines[1..body_start - 1].join("\n");
+            if yaml.trim().is_empty() {
+                PrdMetadata::default()
+            } else {
+                serde_yaml::from_str(&yaml).map_err(|err| {
+                    LaunError::prd("failed to parse YAML frontmatter").with_source(err)
+                })?
+            }
+        };
+        let items = scan_items(&lines)
+            .into_iter()
+            .map(|item| PrdItem {
+                text: item.text.trim().to_string(),
+                normalized: normalize_text(item.text),
+                status: item.status,
+                line: item.index + 1,
+                depth: item.depth,
+                section: item.section.map(str::to_string),
+                tags: parse_tags(item.text),
+                acceptance: item
+                    .acceptance
+                    .iter()
+                    .filter(|line| !is_annotation(line))
+                    .map(|line| line.to_string())
+                    .collect(),
+                done_when: annotation_values(&item.acceptance, "done when")
+                    .first()
+                    .map(|value| value.to_string()),
+                files: annotation_list(&item.acceptance, "files"),
+                priority: annotation_values(&item.acceptance, "priority")
+                    .first()
+                    .map(|value| value.to_string()),
+                estimate: annotation_values(&item.acceptance, "estimate")
+                    .first()
+                    .map(|value| value.to_string()),
+                depends_on: annotation_list(&item.acceptance, "depends on"),
+            })
+            .collect();
+
+        Ok(Self { metadata, items })
+    }
+
+    pub fn find_item(&self, target_item: &str) -> Option<&PrdItem> {
+        self.find_item_with(target_item, &MatchOptions::default())
+    }
+
+    pub fn find_item_with(&self, target_item: &str, options: &MatchOptions) -> Option<&PrdItem> {
+        let candidates = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(id, item)| Candidate {
+                id,
+                status: item.status,
+                text: &item.text,
+                depth: item.depth,
+                section: item.section.as_deref(),
+            })
+            .collect();
+        match match_item(candidates, target_item, options, |_| true) {
+            LineMatch::Unique(id) => self.items.get(id),
+            LineMatch::None | LineMatch::Ambiguous(_) => None,
+        }
+    }
+
+    pub fn changes_since(&self, previous: &PrdDocument) -> PrdChanges {
+        let mut removed: Vec<&PrdItem> = previous.items.iter().collect();
+        let mut added = Vec::new();
+        for item in &self.items {
+            match removed
+                .iter()
+                .position(|old| old.normalized == item.normalized)
             {
-                items.push(PrdItem {
-                    text: text.trim().to_string(),
-                    checked: true,
-                });
+                Some(position) => {
+                    removed.remove(position);
+                }
+                None => added.push(item.text.clone()),
             }
         }
+        PrdChanges {
+            added,
+            removed: removed.into_iter().map(|item| item.text.clone()).collect(),
+        }
+    }
 
-        Self { items }
+    pub fn sections(&self) -> Vec<(Option<&str>, Vec<&PrdItem>)> {
+        let mut sections: Vec<(Option<&str>, Vec<&PrdItem>)> = Vec::new();
+        for item in &self.items {
+            let section = item.section.as_deref();
+            match sections.iter_mut().find(|(name, _)| *name == section) {
+                Some((_, items)) => items.push(item),
+                None => sections.push((section, vec![item])),
+            }
+        }
+        sections
+    }
+
+    pub fn progress(&self) -> Progress {
+        Progress::of(&self.items.iter().collect::<Vec<_>>())
     }
 
     pub fn unchecked_items(&self) -> Vec<&PrdItem> {
-        self.items.iter().filter(|it| !it.checked).collect()
+        self.items
+            .iter()
+            .filter(|it| it.status.is_claimable())
+            .collect()
+    }
+
+    pub fn retain_claimable(&mut self, filter: &ItemFilter) {
+        self.items
+            .retain(|item| !item.status.is_claimable() || filter.matches(item));
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    pub total: usize,
+    pub done: usize,
+    pub remaining: usize,
+    pub percent: usize,
+}
+
+impl Progress {
+    pub fn of(items: &[&PrdItem]) -> Self {
+        let total = items.len();
+        let done = items
+            .iter()
+            .filter(|item| item.status == ItemStatus::Done)
+            .count();
+        Self {
+            total,
+            done,
+            remaining: total - done,
+            percent: (done * 100).checked_div(total).unwrap_or(0),
+        }
+    }
+
+    pub fn bar(&self, width: usize) -> String {
+        let filled = (self.done * width).checked_div(self.total).unwrap_or(0);
+        format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrdChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl PrdChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl fmt::Display for PrdChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |items: &[String]| {
+            items
+                .iter()
+                .map(|item| format!("`{item}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut parts = Vec::new();
+        if !self.added.is_empty() {
+            parts.push(format!("added {}", list(&self.added)));
+        }
+        if !self.removed.is_empty() {
+            parts.push(format!("removed {}", list(&self.removed)));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ItemFilter {
+    pub pattern: Option<Regex>,
+    pub tags: Vec<String>,
+    pub section: Option<String>,
+}
+
+impl ItemFilter {
+    pub fn is_active(&self) -> bool {
+        self.pattern.is_some() || !self.tags.is_empty() || self.section.is_some()
+    }
+
+    pub fn matches(&self, item: &PrdItem) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(&item.text))
+            && self.tags.iter().all(|wanted| {
+                let wanted = wanted.trim_start_matches('#');
+                item.tags.iter().any(|tag| tag.eq_ignore_ascii_case(wanted))
+            })
+            && self
+                .section
+                .as_deref()
+                .is_none_or(|wanted| same_section(item.section.as_deref(), wanted))
     }
 }
 
-pub fn mark_item_done(path: &Path, target_item: &str) -> Result<bool> {
-    let contents = fs::read_to_string(path)
-        .with_context(|| format!("failed to read PRD file {}", path.display()))?;
-    let mut changed = false;
-    let target_norm = normalize(target_item);
+pub(crate) fn parse_tags(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .filter_map(|word| word.strip_prefix('#'))
+        .map(|tag| tag.trim_end_matches(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_')))
+        .filter(|tag| tag.starts_with(|c: char| c.is_alphabetic()))
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintSeverity {
+    Error,
+    Warning,
+}
 
-    let mut rewritten = Vec::new();
-    for line in contents.lines() {
-        if changed {
-            rewritten.push(line.to_string());
+impl fmt::Display for LintSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LintIssue {
+    pub line: usize,
+    pub severity: LintSeverity,
+    pub message: String,
+}
+
+pub fn lint(input: &str) -> Vec<LintIssue> {
+    let lines: Vec<&str> = input.lines().collect();
+    let body_start = frontmatter_len(&lines);
+    let mut issues = Vec::new();
+    let mut items: Vec<(usize, String, &str)> = Vec::new();
+
+    for (index, raw) in lines.iter().enumerate().skip(body_start) {
+        let line = index + 1;
+        let trimmed = raw.trim_end_matches('\r').trim();
+        match parse_checkbox(raw) {
+            Some((_, text)) if normalize_text(text).is_empty() => issues.push(LintIssue {
+                line,
+                severity: LintSeverity::Error,
+                message: "checklist item has no text".to_string(),
+            }),
+            Some((_, text)) => items.push((line, normalize_text(text).to_lowercase(), text.trim())),
+            None if trimmed.len() == 5
+                && trimmed.starts_with("- [")
+                && trimmed.ends_with(']')
+                && trimmed
+                    .chars()
+                    .nth(3)
+                    .is_some_and(|c| ItemStatus::from_marker(c).is_some()) =>
+            {
+                issues.push(LintIssue {
+                    line,
+                    severity: LintSeverity::Error,
+                    message: "checklist item has no text".to_string(),
+                })
+            }
+            None if ["- [", "* [", "+ [", "-[", "[ ]", "[x]"]
+                .iter()
+                .any(|prefix| trimmed.starts_with(prefix)) =>
+            {
+                issues.push(LintIssue {
+                    line,
+                    severity: LintSeverity::Error,
+                    message: format!(
+                        "malformed checkbox `{}`; expected `- [ ] `, `- [-] `, `- [x] ` or `- [~] `",
+                        truncate(trimmed, 40)
+                    ),
+                })
+            }
+            None => {}
+        }
+    }
+
+    for (position, (line, key, text)) in items.iter().enumerate() {
+        for (other_line, other_key, other_text) in &items[..position] {
+            if key == other_key {
+                issues.push(LintIssue {
+                    line: *line,
+                    severity: LintSeverity::Error,
+                    message: format!("`{text}` duplicates line {other_line}"),
+                });
+            } else if other_key.contains(key.as_str()) || key.contains(other_key.as_str()) {
+                issues.push(LintIssue {
+                    line: *line,
+                    severity: LintSeverity::Warning,
+                    message: format!(
+                        "`{text}` and `{other_text}` (line {other_line}) overlap; a target naming the shorter one may match either"
+                    ),
+                });
+            }
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+pub fn check_round_trip(input: &str, case_sensitive: bool) -> Result<Vec<LintIssue>> {
+    let path = Path::new("PRD.md");
+    let original = PrdDocument::parse(input)?;
+    let before: Vec<&str> = input.lines().collect();
+    let options = MatchOptions {
+        case_sensitive,
+        ..MatchOptions::default()
+    };
+    let mut issues = Vec::new();
+    for item in original.unchecked_items() {
+        let fs = MemoryFs::new().with_file(path, input);
+        let problem = match mark_item_done_in(&fs, path, &item.text, &options)? {
+            MarkOutcome::Marked => {
+                round_trip_problem(&original, item, &before, &fs.get(path).unwrap_or_default())
+            }
+            MarkOutcome::NotFound => {
+                Some("marking this item by its text does not find it".to_string())
+            }
+            MarkOutcome::Ambiguous(lines) => Some(format!(
+                "marking this item by its text is ambiguous (lines {})",
+                join_lines(&lines)
+            )),
+        };
+        if let Some(message) = problem {
+            issues.push(LintIssue {
+                line: item.line,
+                severity: LintSeverity::Error,
+                message,
+            });
+        }
+    }
+    Ok(issues)
+}
+
+fn round_trip_problem(
+    original: &PrdDocument,
+    item: &PrdItem,
+    before: &[&str],
+    rewritten: &str,
+) -> Option<String> {
+    let after: Vec<&str> = rewritten.lines().collect();
+    if after.len() != before.len() {
+        return Some(format!(
+            "marking this item changes the line count from {} to {}",
+            before.len(),
+            after.len()
+        ));
+    }
+    let changed: Vec<usize> = (0..before.len())
+        .filter(|index| before[*index] != after[*index])
+        .map(|index| index + 1)
+        .collect();
+    if changed != [item.line] {
+        return Some(format!(
+            "marking this item rewrites line(s) {} instead of line {}",
+            join_lines(&changed),
+            item.line
+        ));
+    }
+    let Ok(reparsed) = PrdDocument::parse(rewritten) else {
+        return Some("the PRD no longer parses after marking this item".to_string());
+    };
+    let expected: Vec<(&str, ItemStatus)> = original
+        .items
+        .iter()
+        .map(|other| {
+            let status = if other.line == item.line {
+                ItemStatus::Done
+            } else {
+                other.status
+            };
+            (other.normalized.as_str(), status)
+        })
+        .collect();
+    let actual: Vec<(&str, ItemStatus)> = reparsed
+        .items
+        .iter()
+        .map(|other| (other.normalized.as_str(), other.status))
+        .collect();
+    (expected != actual).then(|| {
+        "re-parsing the PRD after marking this item yields a different item set".to_string()
+    })
+}
+
+fn join_lines(lines: &[usize]) -> String {
+    lines
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+struct LineItem<'a> {
+    index: usize,
+    status: ItemStatus,
+    text: &'a str,
+    depth: usize,
+    section: Option<&'a str>,
+    acceptance: Vec<&'a str>,
+}
+
+fn scan_items<S: AsRef<str>>(lines: &[S]) -> Vec<LineItem<'_>> {
+    let mut items = Vec::new();
+    let mut section = None;
+    let mut indents: Vec<usize> = Vec::new();
+    for (index, line) in lines.iter().enumerate().skip(frontmatter_len(lines)) {
+        let line = line.as_ref();
+        if let Some(heading) = parse_heading(line) {
+            section = Some(heading);
+            indents.clear();
             continue;
         }
+        let Some((status, text)) = parse_checkbox(line) else {
+            continue;
+        };
+        let indent = indent_width(line);
+        while indents.last().is_some_and(|last| *last >= indent) {
+            indents.pop();
+        }
+        items.push(LineItem {
+            index,
+            status,
+            text,
+            depth: indents.len(),
+            section,
+            acceptance: acceptance_lines(lines, index, indent),
+        });
+        indents.push(indent);
+    }
+    items
+}
+
+fn acceptance_lines<S: AsRef<str>>(lines: &[S], index: usize, indent: usize) -> Vec<&str> {
+    lines[index + 1..]
+        .iter()
+        .map(AsRef::as_ref)
+        .take_while(|line| {
+            !line.trim().is_empty()
+                && indent_width(line) > indent
+                && parse_heading(line).is_none()
+                && parse_checkbox(line).is_none()
+        })
+        .filter_map(|line| {
+            let trimmed = line.trim();
+            ["- ", "* ", "+ "]
+                .iter()
+                .find_map(|marker| trimmed.strip_prefix(marker))
+                .map(str::trim)
+                .filter(|text| !text.is_empty())
+        })
+        .collect()
+}
 
-        let trimmed = line.trim_start();
-        if let Some(text) = trimmed.strip_prefix("- [ ] ") {
-            let text_norm = normalize(text);
-            if text_norm == target_norm || text_norm.contains(&target_norm) {
-                let prefix_len = line.len() - trimmed.len();
-                let prefix = &line[..prefix_len];
-                rewritten.push(format!("{prefix}- [x] {}", text.trim()));
-                changed = true;
-                continue;
+const ANNOTATIONS: &[&str] = &["done when", "files", "priority", "estimate", "depends on"];
+
+fn is_annotation(line: &str) -> bool {
+    ANNOTATIONS.iter().any(|label| {
+        annotation(line, label).is_some_and(|value| !strip_backticks(value).is_empty())
+    })
+}
+
+fn annotation_values<'a>(lines: &[&'a str], label: &str) -> Vec<&'a str> {
+    lines
+        .iter()
+        .filter_map(|line| annotation(line, label))
+        .map(strip_backticks)
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+fn annotation_list(lines: &[&str], label: &str) -> Vec<String> {
+    annotation_values(lines, label)
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .map(strip_backticks)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn annotation<'a>(line: &'a str, label: &str) -> Option<&'a str> {
+    let (name, value) = line.split_once(':')?;
+    name.trim()
+        .eq_ignore_ascii_case(label)
+        .then_some(value.trim())
+}
+
+fn strip_backticks(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('`')
+        .and_then(|rest| rest.strip_suffix('`'))
+        .unwrap_or(value)
+        .trim()
+}
+
+fn heading_level(line: &str) -> usize {
+    line.trim().chars().take_while(|c| *c == '#').count()
+}
+
+fn parse_heading(line: &str) -> Option<&str> {
+    let trimmed = line.trim();
+    let rest = trimmed.trim_start_matches('#');
+    if rest.len() == trimmed.len() || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    Some(rest.trim())
+}
+
+fn indent_width(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+fn frontmatter_len<S: AsRef<str>>(lines: &[S]) -> usize {
+    let is_fence = |line: &S| line.as_ref().trim_end() == "---";
+    match lines.first() {
+        Some(first) if is_fence(first) => lines[1..]
+            .iter()
+            .position(is_fence)
+            .map_or(0, |close| close + 2),
+        _ => 0,
+    }
+}
+
+fn parse_checkbox(line: &str) -> Option<(ItemStatus, &str)> {
+    let rest = line
+        .trim_end_matches('\r')
+        .trim_start()
+        .strip_prefix("- [")?;
+    let mut chars = rest.chars();
+    let status = ItemStatus::from_marker(chars.next()?)?;
+    let text = chars.as_str().strip_prefix("] ")?;
+    Some((status, text))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarkOutcome {
+    Marked,
+    NotFound,
+    Ambiguous(Vec<usize>),
+}
+
+pub fn mark_item_done(
+    path: &Path,
+    target_item: &str,
+    options: &MatchOptions,
+) -> Result<MarkOutcome> {
+    mark_item_done_in(&RealFs, path, target_item, options)
+}
+
+pub fn mark_item_done_in(
+    fs: &dyn FileSystem,
+    path: &Path,
+    target_item: &str,
+    options: &MatchOptions,
+) -> Result<MarkOutcome> {
+    set_marker(fs, path, 'x', |lines| {
+        find_item_line(lines, target_item, options, ItemStatus::is_claimable)
+    })
+}
+
+pub fn mark_line_done(path: &Path, line: usize) -> Result<bool> {
+    mark_line_done_in(&RealFs, path, line)
+}
+
+pub fn mark_line_done_in(fs: &dyn FileSystem, path: &Path, line: usize) -> Result<bool> {
+    let outcome = set_marker(fs, path, 'x', |lines| {
+        let claimable = line.checked_sub(1).filter(|index| {
+            *index >= frontmatter_len(lines)
+                && lines
+                    .get(*index)
+                    .and_then(|line| parse_checkbox(line))
+                    .is_some_and(|(status, _)| status.is_claimable())
+        });
+        match claimable {
+            Some(index) => LineMatch::Unique(index),
+            None => LineMatch::None,
+        }
+    })?;
+    Ok(outcome == MarkOutcome::Marked)
+}
+
+enum LineMatch {
+    Unique(usize),
+    None,
+    Ambiguous(Vec<usize>),
+}
+
+pub fn uncheck_item(path: &Path, target_item: &str, options: &MatchOptions) -> Result<MarkOutcome> {
+    set_marker(&RealFs, path, ' ', |lines| {
+        find_item_line(lines, target_item, options, |status| {
+            status == ItemStatus::Done
+        })
+    })
+}
+
+pub fn claim_item_in(
+    fs: &dyn FileSystem,
+    path: &Path,
+    target_item: &str,
+    options: &MatchOptions,
+    marker: char,
+) -> Result<MarkOutcome> {
+    set_marker(fs, path, marker, |lines| {
+        find_item_line(lines, target_item, options, |status| {
+            status == ItemStatus::Todo
+        })
+    })
+}
+
+pub fn release_item_in(
+    fs: &dyn FileSystem,
+    path: &Path,
+    target_item: &str,
+    options: &MatchOptions,
+) -> Result<MarkOutcome> {
+    set_marker(fs, path, ' ', |lines| {
+        find_item_line(lines, target_item, options, |status| {
+            status == ItemStatus::InProgress
+        })
+    })
+}
+
+pub fn split_item_in(
+    fs: &dyn FileSystem,
+    path: &Path,
+    target_item: &str,
+    subtasks: &[String],
+    options: &MatchOptions,
+) -> Result<MarkOutcome> {
+    let subtasks: Vec<&str> = subtasks
+        .iter()
+        .map(|task| task.trim())
+        .filter(|task| !task.is_empty())
+        .collect();
+    if subtasks.is_empty() {
+        return Err(LaunError::prd(
+            "a split needs at least one non-empty subtask",
+        ));
+    }
+    if subtasks.iter().any(|task| task.contains('\n')) {
+        return Err(LaunError::prd(
+            "PRD item text must be a single non-empty line",
+        ));
+    }
+    edit_item_line(
+        fs,
+        path,
+        |lines| find_item_line(lines, target_item, options, ItemStatus::is_claimable),
+        |lines, index| {
+            set_line_marker(lines, index, 'x');
+            let indent = indent_width(&lines[index]);
+            let prefix = format!(
+                "{}  ",
+                &lines[index][..lines[index].len() - lines[index].trim_start().len()]
+            );
+            let mut end = index + 1;
+            while end < lines.len()
+                && !lines[end].trim().is_empty()
+                && parse_heading(&lines[end]).is_none()
+                && indent_width(&lines[end]) > indent
+            {
+                end += 1;
             }
+            lines.splice(
+                end..end,
+                subtasks.iter().map(|task| format!("{prefix}- [ ] {task}")),
+            );
+        },
+    )
+}
+
+fn set_marker(
+    fs: &dyn FileSystem,
+    path: &Path,
+    marker: char,
+    find_line: impl FnOnce(&[String]) -> LineMatch,
+) -> Result<MarkOutcome> {
+    edit_item_line(fs, path, find_line, |lines, index| {
+        set_line_marker(lines, index, marker)
+    })
+}
+
+fn set_line_marker(lines: &mut [String], index: usize, marker: char) {
+    let line = &lines[index];
+    let prefix = &line[..line.len() - line.trim_start().len()];
+    let text = parse_checkbox(line).map_or(line.trim_start(), |(_, text)| text);
+    lines[index] = format!("{prefix}- [{marker}] {}", text.trim());
+}
+
+fn edit_item_line(
+    fs: &dyn FileSystem,
+    path: &Path,
+    find_line: impl FnOnce(&[String]) -> LineMatch,
+    edit: impl FnOnce(&mut Vec<String>, usize),
+) -> Result<MarkOutcome> {
+    let mut file = PrdLines::read(fs, path)?;
+    let index = match find_line(&file.lines) {
+        LineMatch::Unique(index) => index,
+        LineMatch::None => return Ok(MarkOutcome::NotFound),
+        LineMatch::Ambiguous(indices) => {
+            return Ok(MarkOutcome::Ambiguous(
+                indices.into_iter().map(|index| index + 1).collect(),
+            ));
         }
+    };
+
+    edit(&mut file.lines, index);
+    file.write(fs, path)?;
+
+    Ok(MarkOutcome::Marked)
+}
+
+struct PrdLines {
+    lines: Vec<String>,
+    line_ending: &'static str,
+    trailing_newline: bool,
+}
+
+impl PrdLines {
+    fn read(fs: &dyn FileSystem, path: &Path) -> Result<Self> {
+        let contents = fs.read_to_string(path).map_err(|err| {
+            LaunError::prd(format!("failed to read PRD file {}", path.display())).with_source(err)
+        })?;
+        Ok(Self {
+            lines: contents
+                .lines()
+                .map(|line| line.trim_end_matches('\r').to_string())
+                .collect(),
+            line_ending: if contents.contains("\r\n") {
+                "\r\n"
+            } else {
+                "\n"
+            },
+            trailing_newline: contents.ends_with('\n'),
+        })
+    }
+
+    fn render(&self) -> String {
+        let mut output = self.lines.join(self.line_ending);
+        if self.trailing_newline {
+            output.push_str(self.line_ending);
+        }
+        output
+    }
+
+    fn write(&self, fs: &dyn FileSystem, path: &Path) -> Result<()> {
+        fs.write(path, &self.render()).map_err(|err| {
+            LaunError::prd(format!("failed to write PRD file {}", path.display())).with_source(err)
+        })
+    }
+}
 
-        rewritten.push(line.to_string());
+pub fn append_item(path: &Path, text: &str, heading: Option<&str>) -> Result<usize> {
+    let text = text.trim();
+    if text.is_empty() || text.contains('\n') {
+        return Err(LaunError::prd(
+            "PRD item text must be a single non-empty line",
+        ));
+    }
+    let mut file = PrdLines::read(&RealFs, path)?;
+    let start = frontmatter_len(&file.lines);
+    let mut range = start..file.lines.len();
+    if let Some(heading) = heading {
+        let wanted = heading.trim().trim_start_matches('#').trim();
+        let found =
+            file.lines.iter().enumerate().skip(start).find(|(_, line)| {
+                parse_heading(line).is_some_and(|h| h.eq_ignore_ascii_case(wanted))
+            });
+        let Some((index, line)) = found else {
+            while file.lines.last().is_some_and(|line| line.trim().is_empty()) {
+                file.lines.pop();
+            }
+            if !file.lines.is_empty() {
+                file.lines.push(String::new());
+            }
+            file.lines.push(format!("## {wanted}"));
+            file.lines.push(format!("- [ ] {text}"));
+            file.trailing_newline = true;
+            file.write(&RealFs, path)?;
+            return Ok(file.lines.len());
+        };
+        let level = heading_level(line);
+        let end = file.lines[index + 1..]
+            .iter()
+            .position(|line| parse_heading(line).is_some() && heading_level(line) <= level)
+            .map_or(file.lines.len(), |offset| index + 1 + offset);
+        range = index + 1..end;
     }
 
-    if changed {
-        let mut output = rewritten.join("\n");
-        if contents.ends_with('\n') {
-            output.push('\n');
+    let items: Vec<LineItem<'_>> = scan_items(&file.lines)
+        .into_iter()
+        .filter(|item| range.contains(&item.index))
+        .collect();
+    let (position, prefix) = match (items.iter().rfind(|item| item.depth == 0), items.last()) {
+        (Some(top), Some(last)) => {
+            let top_line = &file.lines[top.index];
+            let prefix = top_line[..top_line.len() - top_line.trim_start().len()].to_string();
+            let indent = indent_width(top_line);
+            let mut end = last.index + 1;
+            while end < range.end
+                && !file.lines[end].trim().is_empty()
+                && indent_width(&file.lines[end]) > indent
+            {
+                end += 1;
+            }
+            (end, prefix)
         }
-        fs::write(path, output)
-            .with_context(|| format!("failed to write PRD file {}", path.display()))?;
+        _ if heading.is_some() => (range.start, String::new()),
+        _ => (range.end, String::new()),
+    };
+    file.lines.insert(position, format!("{prefix}- [ ] {text}"));
+    file.write(&RealFs, path)?;
+    Ok(position + 1)
+}
+
+pub fn parse_task_list(input: &str) -> Result<Vec<String>> {
+    let json = serde_json::from_str::<serde_json::Value>(input).ok();
+    let raw: Vec<String> = if let Some(array @ serde_json::Value::Array(_)) = json {
+        serde_json::from_value(array).map_err(|err| {
+            LaunError::prd("expected a JSON array of task strings").with_source(err)
+        })?
+    } else {
+        input
+            .lines()
+            .map(|line| {
+                let line = line.trim();
+                parse_checkbox(line)
+                    .map(|(_, text)| text)
+                    .or_else(|| line.strip_prefix("[ ] "))
+                    .or_else(|| line.strip_prefix("- "))
+                    .or_else(|| line.strip_prefix("* "))
+                    .unwrap_or(line)
+                    .to_string()
+            })
+            .collect()
+    };
+    let mut seen = Vec::new();
+    let mut tasks = Vec::new();
+    for task in raw {
+        let task = task.split_whitespace().collect::<Vec<_>>().join(" ");
+        let key = normalize_text(&task).to_lowercase();
+        if task.is_empty() || seen.contains(&key) {
+            continue;
+        }
+        seen.push(key);
+        tasks.push(task);
     }
+    Ok(tasks)
+}
 
-    Ok(changed)
+pub fn import_items(path: &Path, tasks: &[String], heading: Option<&str>) -> Result<usize> {
+    let existing: Vec<String> = PrdDocument::load(path)?
+        .items
+        .iter()
+        .map(|item| item.normalized.to_lowercase())
+        .collect();
+    let mut added = 0;
+    for task in tasks {
+        if existing.contains(&normalize_text(task).to_lowercase()) {
+            continue;
+        }
+        append_item(path, task, heading)?;
+        added += 1;
+    }
+    Ok(added)
 }
 
-fn normalize(s: &str) -> String {
-    s.trim().to_lowercase()
+pub fn archive_completed(prd_path: &Path, archive_path: &Path, date: &str) -> Result<usize> {
+    let mut file = PrdLines::read(&RealFs, prd_path)?;
+    let done: Vec<(usize, Option<String>)> = scan_items(&file.lines)
+        .into_iter()
+        .filter(|item| item.status == ItemStatus::Done)
+        .map(|item| (item.index, item.section.map(str::to_string)))
+        .collect();
+    if done.is_empty() {
+        return Ok(0);
+    }
+
+    let mut moved = vec![false; file.lines.len()];
+    let mut archived = format!("## Archived {date}\n");
+    let mut current_section = None;
+    for (position, (index, section)) in done.iter().enumerate() {
+        if position == 0 || *section != current_section {
+            archived.push('\n');
+            if let Some(section) = section {
+                archived.push_str(&format!("### {section}\n\n"));
+            }
+            current_section = section.clone();
+        }
+        let indent = indent_width(&file.lines[*index]);
+        let mut end = index + 1;
+        while end < file.lines.len()
+            && !file.lines[end].trim().is_empty()
+            && indent_width(&file.lines[end]) > indent
+            && parse_checkbox(&file.lines[end]).is_none()
+        {
+            end += 1;
+        }
+        for (offset, line) in file.lines[*index..end].iter().enumerate() {
+            moved[index + offset] = true;
+            let strip = line.len() - line.trim_start().len();
+            archived.push_str(&line[strip.min(indent)..]);
+            archived.push('\n');
+        }
+    }
+
+    let backup = prd_path.with_file_name(format!(
+        "{}.bak",
+        prd_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    ));
+    fs::copy(prd_path, &backup).map_err(|err| {
+        LaunError::prd(format!("failed to back up PRD to {}", backup.display())).with_source(err)
+    })?;
+
+    let mut existing = match fs::read_to_string(archive_path) {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            return Err(LaunError::prd(format!(
+                "failed to read archive {}",
+                archive_path.display()
+            ))
+            .with_source(err));
+        }
+    };
+    if !existing.is_empty() {
+        if !existing.ends_with('\n') {
+            existing.push('\n');
+        }
+        existing.push('\n');
+    }
+    existing.push_str(&archived);
+    fs::write(archive_path, existing).map_err(|err| {
+        LaunError::prd(format!(
+            "failed to write archive {}",
+            archive_path.display()
+        ))
+        .with_source(err)
+    })?;
+
+    file.lines = file
+        .lines
+        .into_iter()
+        .zip(moved)
+        .filter_map(|(line, moved)| (!moved).then_some(line))
+        .collect();
+    file.write(&RealFs, prd_path)?;
+
+    Ok(done.len())
+}
+
+fn find_item_line(
+    lines: &[String],
+    target_item: &str,
+    options: &MatchOptions,
+    wanted: impl Fn(ItemStatus) -> bool,
+) -> LineMatch {
+    let candidates = scan_items(lines)
+        .into_iter()
+        .map(|item| Candidate {
+            id: item.index,
+            status: item.status,
+            text: item.text,
+            depth: item.depth,
+            section: item.section,
+        })
+        .collect();
+    match_item(candidates, target_item, options, wanted)
+}
+
+struct Candidate<'a> {
+    id: usize,
+    status: ItemStatus,
+    text: &'a str,
+    depth: usize,
+    section: Option<&'a str>,
+}
+
+fn match_item(
+    items: Vec<Candidate<'_>>,
+    target_item: &str,
+    options: &MatchOptions,
+    wanted: impl Fn(ItemStatus) -> bool,
+) -> LineMatch {
+    let key = |s: &str| {
+        let normalized = normalize_text(s);
+        if options.case_sensitive {
+            normalized
+        } else {
+            normalized.to_lowercase()
+        }
+    };
+    let in_section = |section: Option<&str>| {
+        options
+            .section
+            .as_deref()
+            .is_none_or(|wanted| same_section(section, wanted))
+    };
+    let scoped = options.section.is_some() && items.iter().any(|item| in_section(item.section));
+    let candidates: Vec<(usize, String, bool)> = items
+        .into_iter()
+        .filter(|item| wanted(item.status) && (!scoped || in_section(item.section)))
+        .map(|item| {
+            let hinted =
+                options.depth.is_none_or(|depth| item.depth == depth) && in_section(item.section);
+            (item.id, key(item.text), hinted)
+        })
+        .collect();
+
+    let target = key(target_item);
+    let hinted = || candidates.iter().filter(|(_, _, hinted)| *hinted);
+    if let Some((index, _, _)) = hinted()
+        .find(|(_, text, _)| *text == target)
+        .or_else(|| candidates.iter().find(|(_, text, _)| *text == target))
+    {
+        return LineMatch::Unique(*index);
+    }
+
+    let partial = |matches: Vec<usize>| match matches.as_slice() {
+        [] => None,
+        [index] => Some(LineMatch::Unique(*index)),
+        _ => Some(LineMatch::Ambiguous(matches)),
+    };
+    let contains =
+        |(index, text, _): &(usize, String, bool)| text.contains(&target).then_some(*index);
+    partial(hinted().filter_map(contains).collect())
+        .or_else(|| partial(candidates.iter().filter_map(contains).collect()))
+        .unwrap_or(LineMatch::None)
+}
+
+fn same_section(section: Option<&str>, wanted: &str) -> bool {
+    section.is_some_and(|section| {
+        normalize_text(section).to_lowercase() == normalize_text(wanted).to_lowercase()
+    })
+}
+
+fn normalize_text(s: &str) -> String {
+    s.replace("**", "")
+        .replace('`', "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_crlf_items_without_carriage_returns() {
+        let doc = PrdDocument::parse("# PRD\r\n- [ ] First item\r\n- [x] Second item\r\n").unwrap();
+
+        assert_eq!(doc.items.len(), 2);
+        assert_eq!(doc.items[0].text, "First item");
+        assert_eq!(doc.items[0].status, ItemStatus::Todo);
+        assert_eq!(doc.items[1].text, "Second item");
+        assert_eq!(doc.items[1].status, ItemStatus::Done);
+    }
+
+    #[test]
+    fn mark_item_done_preserves_crlf_line_endings() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            "# PRD\r\n- [ ] First item\r\n- [ ] Second item\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            mark_item_done(file.path(), "first item", &MatchOptions::default()).unwrap(),
+            MarkOutcome::Marked
+        );
+
+        let rewritten = fs::read_to_string(file.path()).unwrap();
+        assert_eq!(
+            rewritten,
+            "# PRD\r\n- [x] First item\r\n- [ ] Second item\r\n"
+        );
+        let doc = PrdDocument::parse(&rewritten).unwrap();
+        assert_eq!(doc.unchecked_items().len(), 1);
+        assert_eq!(doc.items[0].text, "First item");
+    }
+
+    #[test]
+    fn normalized_text_collapses_whitespace_and_inline_markup() {
+        let doc = PrdDocument::parse("- [ ] Add   **retry**  path for `cargo  test`\n").unwrap();
+
+        assert_eq!(doc.items[0].text, "Add   **retry**  path for `cargo  test`");
+        assert_eq!(doc.items[0].normalized, "Add retry path for cargo test");
+    }
+
+    #[test]
+    fn mark_item_done_matches_target_with_different_spacing() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            "- [ ] Add   **retry**  path\n- [ ] Wire  up\tthe CLI\n",
+        )
+        .unwrap();
+
+        let case_sensitive = MatchOptions {
+            case_sensitive: true,
+            ..MatchOptions::default()
+        };
+        assert_eq!(
+            mark_item_done(file.path(), "Wire up the  CLI", &case_sensitive).unwrap(),
+            MarkOutcome::Marked
+        );
+        assert_eq!(
+            mark_item_done(file.path(), "add retry path", &MatchOptions::default()).unwrap(),
+            MarkOutcome::Marked
+        );
+
+        let rewritten = fs::read_to_string(file.path()).unwrap();
+        assert_eq!(
+            rewritten,
+            "- [x] Add   **retry**  path\n- [x] Wire  up\tthe CLI\n"
+        );
+    }
+
+    #[test]
+    fn frontmatter_is_parsed_and_skipped_for_items() {
+        let doc = PrdDocument::parse(
+            "---\nmax_iterations: 3\nexecution_tests:\n  - cargo check\n---\n# PRD\n- [ ] Only item\n",
+        )
+        .unwrap();
+
+        assert_eq!(doc.metadata.max_iterations, Some(3));
+        assert_eq!(
+            doc.metadata.execution_tests,
+            Some(vec!["cargo check".to_string()])
+        );
+        assert_eq!(doc.metadata.max_fix_attempts, None);
+        assert_eq!(doc.items.len(), 1);
+        assert_eq!(doc.items[0].text, "Only item");
+    }
+
+    #[test]
+    fn items_record_source_lines_and_can_be_marked_by_line() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            "---\nmax_iterations: 2\n---\n# PRD\n- [ ] First\n  continued\n- [ ] First\n",
+        )
+        .unwrap();
+
+        let doc = PrdDocument::load(file.path()).unwrap();
+        assert_eq!(doc.items[0].line, 5);
+        assert_eq!(doc.items[1].line, 7);
+
+        assert!(!mark_line_done(file.path(), 6).unwrap());
+        assert!(mark_line_done(file.path(), 7).unwrap());
+        assert!(!mark_line_done(file.path(), 7).unwrap());
+
+        let rewritten = fs::read_to_string(file.path()).unwrap();
+        assert!(rewritten.ends_with("- [ ] First\n  continued\n- [x] First\n"));
+    }
+
+    #[test]
+    fn lint_reports_duplicates_overlaps_and_malformed_items() {
+        let issues = lint(
+            "# PRD\n- [ ] Add login\n- [ ] add  LOGIN\n- [ ] Add login page\n- [ ]\n- [ ]Missing space\n* [ ] Star bullet\n",
+        );
+        let summary: Vec<(usize, LintSeverity)> = issues
+            .iter()
+            .map(|issue| (issue.line, issue.severity))
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (3, LintSeverity::Error),
+                (4, LintSeverity::Warning),
+                (4, LintSeverity::Warning),
+                (5, LintSeverity::Error),
+                (6, LintSeverity::Error),
+                (7, LintSeverity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_truncates_multibyte_malformed_items_on_char_boundaries() {
+        let line = format!("- []{}", "é".repeat(60));
+        let issues = lint(&format!("# PRD\n{line}\n"));
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, LintSeverity::Error);
+        assert!(
+            issues[0]
+                .message
+                .contains(&format!("`- []{}...`", "é".repeat(36)))
+        );
+    }
+
+    #[test]
+    fn depth_and_section_hints_pick_between_similar_items() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            "## Backend\n- [ ] Add auth\n  - [ ] Add auth tests\n## Frontend\n- [ ] Add auth tests\n",
+        )
+        .unwrap();
+
+        let doc = PrdDocument::load(file.path()).unwrap();
+        let outline: Vec<(usize, Option<&str>)> = doc
+            .items
+            .iter()
+            .map(|item| (item.depth, item.section.as_deref()))
+            .collect();
+        assert_eq!(
+            outline,
+            vec![
+                (0, Some("Backend")),
+                (1, Some("Backend")),
+                (0, Some("Frontend"))
+            ]
+        );
+
+        let top_level = MatchOptions {
+            depth: Some(0),
+            ..MatchOptions::default()
+        };
+        assert_eq!(
+            mark_item_done(file.path(), "Add auth tests", &top_level).unwrap(),
+            MarkOutcome::Marked
+        );
+        let backend = MatchOptions {
+            section: Some("backend".to_string()),
+            ..MatchOptions::default()
+        };
+        assert_eq!(
+            mark_item_done(file.path(), "Add auth", &backend).unwrap(),
+            MarkOutcome::Marked
+        );
+
+        let rewritten = fs::read_to_string(file.path()).unwrap();
+        assert_eq!(
+            rewritten,
+            "## Backend\n- [x] Add auth\n  - [ ] Add auth tests\n## Frontend\n- [x] Add auth tests\n"
+        );
+    }
+
+    #[test]
+    fn tags_and_pattern_filters_are_combined() {
+        let mut doc = PrdDocument::parse(
+            "- [ ] Build API #backend #urgent\n- [ ] Build UI #frontend\n- [ ] Fix issue #12 in API\n- [x] Done API #backend\n",
+        )
+        .unwrap();
+        assert_eq!(doc.items[0].tags, vec!["backend", "urgent"]);
+        assert!(doc.items[2].tags.is_empty());
+
+        let filter = ItemFilter {
+            pattern: Some(Regex::new("API").unwrap()),
+            tags: vec!["#Backend".to_string()],
+            section: None,
+        };
+        doc.retain_claimable(&filter);
+        let texts: Vec<&str> = doc.items.iter().map(|item| item.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["Build API #backend #urgent", "Done API #backend"]
+        );
+    }
+
+    #[test]
+    fn archive_moves_done_items_and_keeps_headings() {
+        let dir = tempfile::tempdir().unwrap();
+        let prd = dir.path().join("PRD.md");
+        let archive = dir.path().join("DONE.md");
+        fs::write(
+            &prd,
+            "# PRD\n\n## API\n- [x] Build API\n  notes on the API\n- [ ] Add paging\n## UI\n- [x] Draw page\n- [~] Dark mode\n",
+        )
+        .unwrap();
+
+        assert_eq!(archive_completed(&prd, &archive, "2026-01-02").unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(&prd).unwrap(),
+            "# PRD\n\n## API\n- [ ] Add paging\n## UI\n- [~] Dark mode\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&archive).unwrap(),
+            "## Archived 2026-01-02\n\n### API\n\n- [x] Build API\n  notes on the API\n\n### UI\n\n- [x] Draw page\n"
+        );
+        assert!(dir.path().join("PRD.md.bak").exists());
+
+        assert_eq!(archive_completed(&prd, &archive, "2026-01-03").unwrap(), 0);
+    }
+
+    #[test]
+    fn append_item_targets_heading_or_last_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let prd = dir.path().join("PRD.md");
+        fs::write(
+            &prd,
+            "# PRD\n\n## Checklist\n- [ ] First\n  - [ ] Nested\n  note\n\n## Notes\n- [ ] Other\n",
+        )
+        .unwrap();
+
+        assert_eq!(append_item(&prd, "Second", Some("checklist")).unwrap(), 7);
+        assert_eq!(append_item(&prd, "Third", None).unwrap(), 11);
+        assert_eq!(append_item(&prd, "Later", Some("Backlog")).unwrap(), 14);
+        assert_eq!(
+            fs::read_to_string(&prd).unwrap(),
+            "# PRD\n\n## Checklist\n- [ ] First\n  - [ ] Nested\n  note\n- [ ] Second\n\n## Notes\n- [ ] Other\n- [ ] Third\n\n## Backlog\n- [ ] Later\n"
+        );
+    }
+
+    #[test]
+    fn in_memory_prd_can_be_loaded_and_marked() {
+        let path = Path::new("/virtual/PRD.md");
+        let fs = MemoryFs::new().with_file(path, "# PRD\n- [ ] First\n- [ ] Second\n");
+
+        assert_eq!(
+            mark_item_done_in(&fs, path, "second", &MatchOptions::default()).unwrap(),
+            MarkOutcome::Marked
+        );
+        assert!(mark_line_done_in(&fs, path, 2).unwrap());
+        assert!(!mark_line_done_in(&fs, path, 2).unwrap());
+
+        let doc = PrdDocument::load_from(&fs, path).unwrap();
+        assert!(doc.unchecked_items().is_empty());
+        assert_eq!(fs.get(path).unwrap(), "# PRD\n- [x] First\n- [x] Second\n");
+        assert!(PrdDocument::load_from(&fs, Path::new("/virtual/missing.md")).is_err());
+    }
+
+    #[test]
+    fn ambiguous_partial_matches_are_not_marked() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            "- [ ] Add login page\n- [ ] Add login tests\n- [ ] Add login\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            mark_item_done(file.path(), "login", &MatchOptions::default()).unwrap(),
+            MarkOutcome::Ambiguous(vec![1, 2, 3])
+        );
+        assert_eq!(
+            mark_item_done(file.path(), "add login", &MatchOptions::default()).unwrap(),
+            MarkOutcome::Marked
+        );
+        assert_eq!(
+            mark_item_done(file.path(), "login page", &MatchOptions::default()).unwrap(),
+            MarkOutcome::Marked
+        );
+        assert_eq!(
+            mark_item_done(file.path(), "signup", &MatchOptions::default()).unwrap(),
+            MarkOutcome::NotFound
+        );
+        assert_eq!(
+            fs::read_to_string(file.path()).unwrap(),
+            "- [x] Add login page\n- [ ] Add login tests\n- [x] Add login\n"
+        );
+    }
+
+    #[test]
+    fn uncheck_item_reopens_only_completed_items() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            "- [x] Ship API\n  - [x] Ship API docs\n- [ ] Ship UI\n",
+        )
+        .unwrap();
+        let top_level = MatchOptions {
+            depth: Some(0),
+            ..MatchOptions::default()
+        };
+
+        assert_eq!(
+            uncheck_item(file.path(), "ship ui", &MatchOptions::default()).unwrap(),
+            MarkOutcome::NotFound
+        );
+        assert_eq!(
+            uncheck_item(file.path(), "Ship API docs", &MatchOptions::default()).unwrap(),
+            MarkOutcome::Marked
+        );
+        assert_eq!(
+            uncheck_item(file.path(), "Ship API", &top_level).unwrap(),
+            MarkOutcome::Marked
+        );
+        assert_eq!(
+            fs::read_to_string(file.path()).unwrap(),
+            "- [ ] Ship API\n  - [ ] Ship API docs\n- [ ] Ship UI\n"
+        );
+    }
+
+    #[test]
+    fn plain_sub_bullets_become_acceptance_criteria() {
+        let doc = PrdDocument::parse(
+            "- [ ] Add paging\n  - accepts `cursor`\n  a free-form note\n  * returns next_cursor\n  - [ ] Paging docs\n    - mentions limits\n- [ ] Other\n",
+        )
+        .unwrap();
+
+        assert_eq!(doc.items.len(), 3);
+        assert_eq!(
+            doc.items[0].acceptance,
+            vec!["accepts `cursor`", "returns next_cursor"]
+        );
+        assert_eq!(doc.items[1].acceptance, vec!["mentions limits"]);
+        assert!(doc.items[2].acceptance.is_empty());
+        assert_eq!(doc.find_item("paging docs").unwrap().line, 5);
+        assert!(doc.find_item("paging").is_none());
+    }
+
+    #[test]
+    fn done_when_bullets_are_parsed_out_of_acceptance_criteria() {
+        let doc = PrdDocument::parse(
+            "- [ ] Add paging\n  - accepts `cursor`\n  - Done when: `cargo test paging`\n- [ ] Other\n  - done when:\n",
+        )
+        .unwrap();
+
+        assert_eq!(doc.items[0].acceptance, vec!["accepts `cursor`"]);
+        assert_eq!(doc.items[0].done_when.as_deref(), Some("cargo test paging"));
+        assert_eq!(doc.items[1].acceptance, vec!["done when:"]);
+        assert_eq!(doc.items[1].done_when, None);
+    }
+
+    #[test]
+    fn metadata_bullets_are_parsed_into_item_fields() {
+        let doc = PrdDocument::parse(
+            "- [ ] Add paging\n  - files: `src/api.rs`, docs/api.md\n  - Files: src/lib.rs\n  - priority: high\n  - estimate: 2h\n  - depends on: Add auth, `Add DB`\n",
+        )
+        .unwrap();
+
+        let item = &doc.items[0];
+        assert!(item.acceptance.is_empty());
+        assert_eq!(item.files, vec!["src/api.rs", "docs/api.md", "src/lib.rs"]);
+        assert_eq!(item.priority.as_deref(), Some("high"));
+        assert_eq!(item.estimate.as_deref(), Some("2h"));
+        assert_eq!(item.depends_on, vec!["Add auth", "Add DB"]);
+    }
+
+    #[test]
+    fn split_item_nests_subtasks_under_the_completed_parent() {
+        let path = Path::new("/virtual/PRD.md");
+        let fs = MemoryFs::new().with_file(
+            path,
+            "## Checklist\n- [ ] Build sync\n  - handles conflicts\n- [ ] Ship docs\n",
+        );
+        let subtasks = vec!["Pull changes".to_string(), " Push changes ".to_string()];
+
+        assert_eq!(
+            split_item_in(&fs, path, "build sync", &subtasks, &MatchOptions::default()).unwrap(),
+            MarkOutcome::Marked
+        );
+        assert_eq!(
+            fs.get(path).unwrap(),
+            "## Checklist\n- [x] Build sync\n  - handles conflicts\n  - [ ] Pull changes\n  - [ ] Push changes\n- [ ] Ship docs\n"
+        );
+        let doc = PrdDocument::load_from(&fs, path).unwrap();
+        assert_eq!(doc.find_item("pull changes").unwrap().depth, 1);
+        assert_eq!(
+            split_item_in(&fs, path, "build sync", &subtasks, &MatchOptions::default()).unwrap(),
+            MarkOutcome::NotFound
+        );
+        assert!(split_item_in(&fs, path, "ship docs", &[], &MatchOptions::default()).is_err());
+    }
+
+    #[test]
+    fn changes_since_reports_added_removed_and_reworded_items() {
+        let before = PrdDocument::parse("- [ ] Keep\n- [ ] Drop\n- [ ] Old wording\n").unwrap();
+        let after =
+            PrdDocument::parse("- [x] Keep\n- [ ] New wording\n- [ ] Urgent fix\n").unwrap();
+
+        let changes = after.changes_since(&before);
+        assert_eq!(changes.added, ["New wording", "Urgent fix"]);
+        assert_eq!(changes.removed, ["Drop", "Old wording"]);
+        assert_eq!(
+            changes.to_string(),
+            "added `New wording`, `Urgent fix`; removed `Drop`, `Old wording`"
+        );
+        assert!(after.changes_since(&after).is_empty());
+    }
+
+    #[test]
+    fn round_trip_check_flags_items_that_cannot_be_marked_cleanly() {
+        let clean = "# PRD\n- [ ] First\n  - [ ] Nested\n- [x] Done\n";
+        assert!(check_round_trip(clean, false).unwrap().is_empty());
+
+        let issues = check_round_trip(
+            "- [ ] Add login\n- [ ] add   LOGIN\n- [ ] Add login page\n",
+            false,
+        )
+        .unwrap();
+        let lines: Vec<usize> = issues.iter().map(|issue| issue.line).collect();
+        assert_eq!(lines, [2]);
+        assert!(issues[0].message.contains("instead of line 2"));
+    }
+
+    #[test]
+    fn find_item_with_uses_the_same_rules_as_marking() {
+        let doc = PrdDocument::parse(
+            "## Backend\n- [ ] Write docs\n  - covers the API\n\n## Frontend\n- [ ] Write docs\n  - covers the UI\n  - [ ] write DOCS\n",
+        )
+        .unwrap();
+        let options = |section: &str, case_sensitive: bool| MatchOptions {
+            case_sensitive,
+            depth: Some(0),
+            section: Some(section.to_string()),
+        };
+
+        let found = doc
+            .find_item_with("Write docs", &options("frontend", false))
+            .unwrap();
+        assert_eq!(
+            (found.line, found.acceptance.as_slice()),
+            (6, &["covers the UI".to_string()][..])
+        );
+        assert_eq!(
+            doc.find_item_with("Write docs", &options("Backend", false))
+                .unwrap()
+                .line,
+            2
+        );
+        assert_eq!(
+            doc.find_item_with("write DOCS", &options("Frontend", true))
+                .unwrap()
+                .line,
+            8
+        );
+        assert!(
+            doc.find_item_with("WRITE DOCS", &options("Frontend", true))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn section_hint_scopes_matching_to_that_heading() {
+        let path = Path::new("/virtual/PRD.md");
+        let input =
+            "## Backend\n- [ ] Write docs\n- [ ] Add cache\n\n## Frontend\n- [ ] Write docs\n";
+        let fs = MemoryFs::new().with_file(path, input);
+        let in_section = |section: &str| MatchOptions {
+            section: Some(section.to_string()),
+            ..MatchOptions::default()
+        };
+
+        let doc = PrdDocument::load_from(&fs, path).unwrap();
+        let grouped: Vec<(Option<&str>, usize)> = doc
+            .sections()
+            .into_iter()
+            .map(|(section, items)| (section, items.len()))
+            .collect();
+        assert_eq!(grouped, [(Some("Backend"), 2), (Some("Frontend"), 1)]);
+
+        assert_eq!(
+            mark_item_done_in(&fs, path, "Write docs", &in_section("frontend")).unwrap(),
+            MarkOutcome::Marked
+        );
+        assert_eq!(
+            fs.get(path).unwrap(),
+            "## Backend\n- [ ] Write docs\n- [ ] Add cache\n\n## Frontend\n- [x] Write docs\n"
+        );
+        assert_eq!(
+            mark_item_done_in(&fs, path, "Add cache", &in_section("Frontend")).unwrap(),
+            MarkOutcome::NotFound
+        );
+        assert_eq!(
+            mark_item_done_in(&fs, path, "Write docs", &in_section("Missing")).unwrap(),
+            MarkOutcome::Marked
+        );
+        assert_eq!(
+            fs.get(path).unwrap(),
+            "## Backend\n- [x] Write docs\n- [ ] Add cache\n\n## Frontend\n- [x] Write docs\n"
+        );
+
+        let filter = ItemFilter {
+            section: Some("backend".to_string()),
+            ..ItemFilter::default()
+        };
+        let doc = PrdDocument::load_from(&fs, path).unwrap();
+        let selected: Vec<usize> = doc
+            .items
+            .iter()
+            .filter(|item| filter.matches(item))
+            .map(|item| item.line)
+            .collect();
+        assert_eq!(selected, [2, 3]);
+    }
+
+    #[test]
+    fn task_lists_import_from_lines_or_json_without_duplicates() {
+        let lines = "- [ ] Add login\n\n* Add  logout\nadd login\n  Write docs  \n";
+        assert_eq!(
+            parse_task_list(lines).unwrap(),
+            ["Add login", "Add logout", "Write docs"]
+        );
+        assert_eq!(
+            parse_task_list(r#"["Ship it", "ship it", "Multi\nline"]"#).unwrap(),
+            ["Ship it", "Multi line"]
+        );
+        assert!(parse_task_list("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn task_lists_starting_with_brackets_are_not_json() {
+        assert_eq!(
+            parse_task_list("[ ] first task\n[ ] second task\n").unwrap(),
+            ["first task", "second task"]
+        );
+        assert_eq!(
+            parse_task_list("[infra] set up CI\n[docs] write the guide [wip]\n").unwrap(),
+            ["[infra] set up CI", "[docs] write the guide [wip]"]
+        );
+    }
+
+    #[test]
+    fn progress_counts_done_items_and_handles_an_empty_prd() {
+        let doc = PrdDocument::parse("- [x] One\n- [ ] Two\n  - [x] Nested\n- [-] Four\n").unwrap();
+        let progress = doc.progress();
+        assert_eq!(
+            (
+                progress.total,
+                progress.done,
+                progress.remaining,
+                progress.percent
+            ),
+            (4, 2, 2, 50)
+        );
+        assert_eq!(progress.bar(8), "[####----]");
+
+        let empty = PrdDocument::parse("# PRD\n").unwrap().progress();
+        assert_eq!((empty.total, empty.percent), (0, 0));
+        assert_eq!(empty.bar(4), "[----]");
+    }
 }
@@ -0,0 +1,567 @@
+use crate::{
+    config::{AgentConfig, AppConfig, PromptOverflow},
+    prd::{ItemStatus, PrdDocument, PrdItem},
+    runner::truncate,
+    template,
+};
+use anyhow::{Context, Result, bail};
+use log::warn;
+use std::{collections::BTreeMap, fs, path::Path};
+
+const DEFAULT_LOOP_TEMPLATE: &str = r#"{{system}}
+
+Role: Loop manager (fast model). Decide the next task for the implementation agent.
+PRD file: {{prd_file}}
+
+Visible files for you:
+{{loop_files}}
+
+Visible tests for you:
+{{loop_tests}}
+
+Execution tests run by orchestrator:
+{{exec_tests}}
+
+Completed PRD items:
+{{completed}}
+
+Remaining PRD items:
+{{remaining}}
+
+Prior orchestration context:
+{{context}}
+
+{{recent_commits}}Respond with JSON only:
+{
+  "action": "delegate" | "split" | "done",
+  "target_item": "exact PRD item text to execute",
+  "worker_prompt": "concrete implementation instructions",
+  "commit_message": "optional commit message",
+  "reason": "optional short rationale",
+  "depth": "optional nesting depth of target_item (0 = top level)",
+  "section": "optional heading that target_item is listed under",
+  "subtasks": ["with action split: smaller PRD items that replace target_item"],
+  "iterations_hint": "optional number of iterations to keep the worker on target_item until it is done"
+}
+"#;
+
+const DEFAULT_WORKER_TEMPLATE: &str = r#"{{system}}
+
+Role: Implementation agent (slower, stronger model).
+Current PRD item:
+{{target_item}}
+
+Task:
+{{worker_task}}
+
+{{acceptance}}You may focus on these files:
+{{files}}
+
+{{file_contents}}You should internally validate against these tests:
+{{tests}}
+
+The orchestrator will run this test suite after your turn:
+{{exec_tests}}
+
+{{failure_block}}
+Keep output concise. Include:
+1) What changed
+2) What remains risky
+3) Suggested commit message
+"#;
+
+const DEFAULT_REVIEW_TEMPLATE: &str = r#"{{system}}
+
+Role: Code reviewer. Review the implementation agent's uncommitted changes for the PRD item below before they are committed. The orchestrator's test suite already passed.
+Current PRD item:
+{{target_item}}
+
+Task given to the implementation agent:
+{{worker_task}}
+
+{{acceptance}}Changes:
+{{diff}}
+
+Respond with JSON only:
+{
+  "verdict": "approve" | "request_changes",
+  "comments": "with request_changes: the concrete changes the implementation agent must make"
+}
+"#;
+
+pub fn build_review_prompt(
+    cfg: &AppConfig,
+    reviewer: &AgentConfig,
+    root: &Path,
+    target: &WorkerTarget,
+    diff: &str,
+) -> Result<String> {
+    let mut vars = cfg.variables.clone();
+    vars.insert(
+        "system".to_string(),
+        template::render(&reviewer.system_prompt, &cfg.variables)?,
+    );
+    vars.insert("target_item".to_string(), target.item.to_string());
+    vars.insert("worker_task".to_string(), target.task.to_string());
+    vars.insert(
+        "acceptance".to_string(),
+        if target.acceptance.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "Acceptance criteria:\n{}\n\n",
+                format_lines(target.acceptance)
+            )
+        },
+    );
+    vars.insert(
+        "diff".to_string(),
+        if diff.trim().is_empty() {
+            "(no changes)".to_string()
+        } else {
+            truncate(diff, 50_000)
+        },
+    );
+    fit_prompt(
+        reviewer,
+        "reviewer",
+        root,
+        DEFAULT_REVIEW_TEMPLATE,
+        vars,
+        &["diff"],
+    )
+}
+
+pub fn build_summary_prompt(target_item: &str, worker_output: &str) -> String {
+    format!(
+        "Summarize the implementation agent's report below in at most five short bullet points.\n\
+         Cover what changed, what failed or remains open, and anything the next task should know.\n\
+         Reply with the bullet points only.\n\n\
+         PRD item: {target_item}\n\n\
+         Report:\n{}\n",
+        truncate(worker_output, 20_000)
+    )
+}
+
+pub fn build_loop_prompt(
+    cfg: &AppConfig,
+    root: &Path,
+    prd_path: &Path,
+    prd: &PrdDocument,
+    loop_context: &str,
+    execution_tests: &[String],
+    recent_commits: &[String],
+) -> Result<String> {
+    let remaining = prd
+        .unchecked_items()
+        .into_iter()
+        .map(|i| {
+            let indent = "  ".repeat(i.depth);
+            let line = match i.status {
+                ItemStatus::InProgress => format!("{indent}- {} (in progress)", i.text),
+                _ => format!("{indent}- {}", i.text),
+            };
+            if cfg.workflow.loop_item_metadata {
+                format!("{line}{}", item_metadata(i))
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let completed = prd
+        .items
+        .iter()
+        .filter(|i| i.status == ItemStatus::Done)
+        .map(|i| format!("{}- {}", "  ".repeat(i.depth), i.text))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let agent = &cfg.loop_agent;
+    let mut vars = cfg.variables.clone();
+    vars.insert(
+        "system".to_string(),
+        template::render(&agent.system_prompt, &cfg.variables)?,
+    );
+    vars.insert("prd_file".to_string(), prd_path.display().to_string());
+    vars.insert(
+        "loop_files".to_string(),
+        format_lines(&visible_files(
+            root,
+            &template::render_all(&agent.visible_files, &cfg.variables)?,
+        )),
+    );
+    vars.insert(
+        "loop_tests".to_string(),
+        format_lines(&template::render_all(&agent.visible_tests, &cfg.variables)?),
+    );
+    vars.insert("exec_tests".to_string(), format_lines(execution_tests));
+    vars.insert("completed".to_string(), or_none(completed));
+    vars.insert("remaining".to_string(), remaining);
+    vars.insert("context".to_string(), or_none(loop_context.to_string()));
+    vars.insert(
+        "recent_commits".to_string(),
+        if recent_commits.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "Recent commits (newest first):\n{}\n\n",
+                format_lines(recent_commits)
+            )
+        },
+    );
+
+    fit_prompt(
+        agent,
+        "loop",
+        root,
+        DEFAULT_LOOP_TEMPLATE,
+        vars,
+        &["context", "loop_files"],
+    )
+}
+
+fn item_metadata(item: &PrdItem) -> String {
+    let mut parts = Vec::new();
+    if let Some(priority) = &item.priority {
+        parts.push(format!("priority: {priority}"));
+    }
+    if let Some(estimate) = &item.estimate {
+        parts.push(format!("estimate: {estimate}"));
+    }
+    if !item.depends_on.is_empty() {
+        parts.push(format!("depends on: {}", item.depends_on.join(", ")));
+    }
+    if !item.acceptance.is_empty() {
+        parts.push(format!("acceptance criteria: {}", item.acceptance.len()));
+    }
+    if item.done_when.is_some() {
+        parts.push("verified by a done_when check".to_string());
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", parts.join("; "))
+    }
+}
+
+pub struct WorkerTarget<'a> {
+    pub item: &'a str,
+    pub task: &'a str,
+    pub acceptance: &'a [String],
+    pub files: &'a [String],
+}
+
+pub fn build_worker_prompt(
+    cfg: &AppConfig,
+    root: &Path,
+    target: &WorkerTarget,
+    failure_output: Option<&str>,
+    execution_tests: &[String],
+) -> Result<String> {
+    let WorkerTarget {
+        item: target_item,
+        task: worker_task,
+        acceptance,
+        files: target_files,
+    } = *target;
+    let failure_block = failure_output
+        .map(|output| {
+            format!(
+                "Previous test failures to fix first:\n{}\n",
+                truncate(output, 3000)
+            )
+        })
+        .unwrap_or_default();
+
+    let agent = &cfg.worker_agent;
+    let mut vars = cfg.variables.clone();
+    vars.insert(
+        "system".to_string(),
+        template::render(&agent.system_prompt, &cfg.variables)?,
+    );
+    vars.insert("target_item".to_string(), target_item.to_string());
+    vars.insert("worker_task".to_string(), worker_task.to_string());
+    vars.insert(
+        "acceptance".to_string(),
+        if acceptance.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "Acceptance criteria (the item is done when all of these hold):\n{}\n\n",
+                format_lines(acceptance)
+            )
+        },
+    );
+    vars.insert(
+        "files".to_string(),
+        format_lines(&visible_files(
+            root,
+            &template::render_all(&agent.visible_files, &cfg.variables)?,
+        )),
+    );
+    vars.insert(
+        "file_contents".to_string(),
+        if agent.inline_file_contents {
+            inline_file_contents(agent, root, target_item, target_files)
+        } else {
+            String::new()
+        },
+    );
+    vars.insert(
+        "tests".to_string(),
+        format_lines(&template::render_all(&agent.visible_tests, &cfg.variables)?),
+    );
+    vars.insert("exec_tests".to_string(), format_lines(execution_tests));
+    vars.insert("failure_block".to_string(), failure_block);
+
+    fit_prompt(
+        agent,
+        "worker",
+        root,
+        DEFAULT_WORKER_TEMPLATE,
+        vars,
+        &["file_contents", "failure_block", "files"],
+    )
+}
+
+fn inline_file_contents(
+    agent: &AgentConfig,
+    root: &Path,
+    target_item: &str,
+    target_files: &[String],
+) -> String {
+    let mut paths: Vec<&str> = target_files.iter().map(String::as_str).collect();
+    paths.extend(
+        target_item
+            .split('`')
+            .skip(1)
+            .step_by(2)
+            .filter(|path| root.join(path).is_file()),
+    );
+    let mut seen = Vec::new();
+    let mut budget = agent.inline_total_max_bytes;
+    let mut blocks = Vec::new();
+    for path in paths {
+        let path = path.trim_start_matches("./");
+        if seen.contains(&path) {
+            continue;
+        }
+        seen.push(path);
+        if Path::new(path).is_absolute() || path.split('/').any(|part| part == "..") {
+            blocks.push(format!(
+                "--- {path} (skipped: outside the project root) ---"
+            ));
+            continue;
+        }
+        let contents = match fs::read_to_string(root.join(path)) {
+            Ok(contents) => contents,
+            Err(err) => {
+                blocks.push(format!("--- {path} (skipped: {err}) ---"));
+                continue;
+            }
+        };
+        if contents.len() > agent.inline_file_max_bytes {
+            blocks.push(format!(
+                "--- {path} (skipped: {} bytes exceeds inline_file_max_bytes) ---",
+                contents.len()
+            ));
+            continue;
+        }
+        if contents.len() > budget {
+            blocks.push(format!(
+                "--- {path} (skipped: inline_total_max_bytes reached) ---"
+            ));
+            continue;
+        }
+        budget -= contents.len();
+        blocks.push(format!(
+            "--- {path} ---\n{}\n--- end of {path} ---",
+            contents.trim_end()
+        ));
+    }
+    if blocks.is_empty() {
+        return String::new();
+    }
+    format!(
+        "Current contents of the targeted files:\n{}\n\n",
+        blocks.join("\n")
+    )
+}
+
+const TRIM_MARKER: &str = "\n[trimmed to fit max_prompt_chars]";
+
+fn fit_prompt(
+    agent: &AgentConfig,
+    role: &str,
+    root: &Path,
+    default_template: &str,
+    mut vars: BTreeMap<String, String>,
+    trimmable: &[&str],
+) -> Result<String> {
+    let mut prompt = render_prompt(agent, root, default_template, &vars)?;
+    let Some(limit) = agent.max_prompt_chars else {
+        return Ok(prompt);
+    };
+    if agent.prompt_overflow == PromptOverflow::Trim {
+        for key in trimmable {
+            let length = prompt.chars().count();
+            if length <= limit {
+                break;
+            }
+            let Some(value) = vars.get(*key) else {
+                continue;
+            };
+            let keep = value
+                .chars()
+                .count()
+                .saturating_sub(length - limit + TRIM_MARKER.len());
+            let trimmed = format!(
+                "{}{TRIM_MARKER}",
+                value.chars().take(keep).collect::<String>()
+            );
+            if trimmed.len() >= value.len() {
+                continue;
+            }
+            warn!("Trimmed `{key}` in the {role} prompt to fit {role}_agent.max_prompt_chars");
+            vars.insert(key.to_string(), trimmed);
+            prompt = render_prompt(agent, root, default_template, &vars)?;
+        }
+    }
+    let length = prompt.chars().count();
+    if length > limit {
+        let (largest, size) = vars
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.chars().count()))
+            .max_by_key(|(_, size)| *size)
+            .unwrap_or(("(template)", 0));
+        bail!(
+            "{role} prompt is {length} characters, over {role}_agent.max_prompt_chars ({limit}); \
+             largest section is `{largest}` ({size} characters)"
+        );
+    }
+    Ok(prompt)
+}
+
+fn render_prompt(
+    agent: &AgentConfig,
+    root: &Path,
+    default_template: &str,
+    vars: &BTreeMap<String, String>,
+) -> Result<String> {
+    let Some(template_file) = &agent.prompt_template else {
+        return template::render(default_template, vars);
+    };
+    let path = root.join(template_file);
+    let raw = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read prompt template {}", path.display()))?;
+    template::render(&raw, vars)
+        .with_context(|| format!("failed to render prompt template {}", path.display()))
+}
+
+struct VisibleEntry {
+    path: String,
+    excluded: Vec<String>,
+}
+
+fn visible_files(root: &Path, patterns: &[String]) -> Vec<String> {
+    let mut entries: Vec<VisibleEntry> = Vec::new();
+    for pattern in patterns {
+        if let Some(negated) = pattern.strip_prefix('!') {
+            let negated = negated.trim_start_matches("./");
+            entries.retain(|entry| !path_matches(negated, &entry.path));
+            for entry in &mut entries {
+                let dir = entry.path.trim_end_matches('/');
+                if negated.starts_with(&format!("{dir}/"))
+                    && !entry.excluded.iter().any(|e| e == negated)
+                {
+                    entry.excluded.push(negated.to_string());
+                }
+            }
+            continue;
+        }
+        for path in expand_pattern(root, pattern) {
+            entries.retain(|entry| entry.path != path);
+            entries.push(VisibleEntry {
+                path,
+                excluded: Vec::new(),
+            });
+        }
+    }
+    entries
+        .into_iter()
+        .map(|entry| {
+            if entry.excluded.is_empty() {
+                entry.path
+            } else {
+                format!("{} (except {})", entry.path, entry.excluded.join(", "))
+            }
+        })
+        .collect()
+}
+
+fn expand_pattern(root: &Path, pattern: &str) -> Vec<String> {
+    let pattern = pattern.trim_start_matches("./");
+    if !pattern.contains(['*', '?', '[']) {
+        return vec![pattern.to_string()];
+    }
+    let full = format!(
+        "{}/{pattern}",
+        glob::Pattern::escape(&root.to_string_lossy())
+    );
+    let mut paths: Vec<String> = glob::glob(&full)
+        .map(|paths| {
+            paths
+                .filter_map(Result::ok)
+                .map(|path| {
+                    let relative = path
+                        .strip_prefix(root)
+                        .unwrap_or(&path)
+                        .to_string_lossy()
+                        .into_owned();
+                    if path.is_dir() {
+                        format!("{relative}/")
+                    } else {
+                        relative
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if paths.is_empty() {
+        paths.push(pattern.to_string());
+    }
+    paths
+}
+
+pub(crate) fn path_matches(pattern: &str, path: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    let pattern = pattern.trim_end_matches('/');
+    path == pattern
+        || path.starts_with(&format!("{pattern}/"))
+        || glob::Pattern::new(pattern).is_ok_and(|glob| {
+            glob.matches(path)
+                || path
+                    .match_indices('/')
+                    .any(|(index, _)| glob.matches(&path[..index]))
+        })
+}
+
+fn format_lines(lines: &[String]) -> String {
+    if lines.is_empty() {
+        return "(none)".to_string();
+    }
+    lines
+        .iter()
+        .map(|line| format!("- {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn or_none(value: String) -> String {
+    if value.is_empty() {
+        "(none)".to_string()
+    } else {
+        value
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::borrow::Cow;
+
+const REPLACEMENT: &str = "***";
+
+const COMMON_SECRET_PATTERNS: &[&str] = &[
+    r"AKIA[0-9A-Z]{16}",
+    r"gh[pousr]_[A-Za-z0-9]{36,}",
+    r"github_pat_[A-Za-z0-9_]{22,}",
+    r"sk-[A-Za-z0-9_-]{20,}",
+    r"xox[abprs]-[A-Za-z0-9-]{10,}",
+    r"AIza[0-9A-Za-z_-]{35}",
+    r"(?i)bearer\s+[A-Za-z0-9._~+/=-]{20,}",
+    r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    pub fn new(patterns: &[String], common_secrets: bool) -> Result<Self> {
+        let builtin: &[&str] = if common_secrets {
+            COMMON_SECRET_PATTERNS
+        } else {
+            &[]
+        };
+        let builtin = builtin
+            .iter()
+            .map(|pattern| Regex::new(pattern).expect("built-in redaction pattern is valid"));
+        let configured = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .with_context(|| format!("invalid workflow.redact_patterns entry `{pattern}`"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            patterns: builtin.chain(configured).collect(),
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.patterns.is_empty()
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut redacted = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            if let Cow::Owned(replaced) = pattern.replace_all(&redacted, REPLACEMENT) {
+                redacted = Cow::Owned(replaced);
+            }
+        }
+        redacted.into_owned()
+    }
+}
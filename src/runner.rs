@@ -1,47 +1,213 @@
 use crate::{
-    agent::CliAgent,
-    config::AppConfig,
-    prd::{PrdDocument, mark_item_done},
+    agent::{self, Agent, AgentError, AgentRunResult, CliAgent},
+    config::{
+        self, AgentConfig, AppConfig, CommitMode, ExitCodeAction, MissingGitAction,
+        OutOfScopeAction, OutputMode, RunWhen, WorkflowConfig,
+    },
+    date, diff, dotenv,
+    error::LaunError,
+    estimate::{self, AgentEstimate, Estimate, ItemEstimate},
+    git::{ChangedPath, Git, MergeConflict},
+    interactive::{self, Pause, Review},
+    logging, patch,
+    prd::{
+        ItemFilter, ItemStatus, MarkOutcome, MatchOptions, PrdDocument, claim_item_in,
+        mark_item_done_in, parse_tags, release_item_in, split_item_in,
+    },
+    process,
+    prompt::{
+        WorkerTarget, build_loop_prompt, build_review_prompt, build_summary_prompt,
+        build_worker_prompt, path_matches,
+    },
+    redact::Redactor,
+    signal,
+    state::{self, FailedItem, RunState},
+    vfs::{FileSystem, MemoryFs, RealFs},
 };
-use anyhow::{Context, Result};
-use serde::Deserialize;
+use anyhow::{Context, Result, anyhow, bail};
+use log::{debug, info, trace, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
+    fmt,
+    io::Read,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
 #[derive(Debug, Clone)]
 pub struct LoopRunner {
     config: AppConfig,
     config_path: PathBuf,
+    project_root: Option<PathBuf>,
+    loop_agent: Option<Arc<dyn Agent>>,
+    worker_agent: Option<Arc<dyn Agent>>,
+    reviewer_agent: Option<Arc<dyn Agent>>,
+    fs: Arc<dyn FileSystem>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct RunOptions {
     pub max_iterations_override: Option<usize>,
     pub dry_run: bool,
+    pub dry_run_worker: bool,
+    pub fail_fast: bool,
+    pub continue_on_agent_error: bool,
+    pub interactive: bool,
+    pub step: bool,
+    pub resume: bool,
+    pub retry_item: bool,
+    pub keep_worktree: bool,
+    pub no_mark: bool,
+    pub allow_dirty: bool,
+    pub verbose_git: bool,
+    pub prd_diff_file: Option<PathBuf>,
+    pub filter: Option<String>,
+    pub tags: Vec<String>,
+    pub section: Option<String>,
+    pub loop_extra_args: Vec<String>,
+    pub worker_extra_args: Vec<String>,
 }
 
-#[derive(Debug, Clone, Default)]
+impl RunOptions {
+    fn item_filter(&self) -> Result<ItemFilter> {
+        Ok(ItemFilter {
+            pattern: self
+                .filter
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .context("invalid --filter pattern")?,
+            tags: self.tags.clone(),
+            section: self.section.clone(),
+        })
+    }
+
+    fn stubs_worker(&self) -> bool {
+        self.dry_run || self.dry_run_worker
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlanStep {
+    pub item: String,
+    pub rationale: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct RunSummary {
+    pub run_id: String,
     pub iterations: usize,
     pub completed_items: usize,
     pub commits: usize,
+    pub failed_iterations: usize,
+    pub fix_attempts: usize,
+    pub duration_secs: f64,
+    pub flaky_items: Vec<String>,
+    pub unmarked_items: Vec<String>,
+    pub skipped_items: Vec<String>,
+    pub empty_targets: usize,
+    pub splits: usize,
+    pub pinned_iterations: usize,
+    pub decision_retries: usize,
+    pub review_change_requests: usize,
+    pub items: Vec<ItemReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prd_diff: Option<String>,
+    pub stop_reason: StopReason,
+}
+
+#[derive(Debug, Clone)]
+pub struct TestOutcome {
+    pub command: String,
+    pub success: bool,
+    pub status: String,
+    pub timed_out: bool,
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemReport {
+    pub item: String,
+    pub commit: Option<String>,
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    Completed,
+    NoItems,
+    LoopAgentDone(String),
+    Interrupted,
+    Aborted,
+    SelectionComplete,
+    AllItemsWorked,
+    OnlySkippedItemsLeft,
+    OnlyClaimedItemsLeft,
+    #[default]
+    IterationLimit,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Completed => write!(f, "all PRD items are complete"),
+            Self::NoItems => write!(f, "no checklist items found in PRD"),
+            Self::LoopAgentDone(reason) => write!(f, "loop agent decided to stop: {reason}"),
+            Self::Interrupted => write!(f, "interrupted"),
+            Self::Aborted => write!(f, "aborted by operator"),
+            Self::SelectionComplete => write!(f, "all selected PRD items are complete"),
+            Self::OnlySkippedItemsLeft => write!(f, "only items the worker skipped are left"),
+            Self::OnlyClaimedItemsLeft => {
+                write!(f, "only items claimed as in progress elsewhere are left")
+            }
+            Self::AllItemsWorked => {
+                write!(f, "every open PRD item was worked; review and mark them")
+            }
+            Self::IterationLimit => write!(f, "iteration limit reached"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoopDecision {
+    pub action: LoopAction,
+    pub target_item: Option<String>,
+    pub worker_prompt: Option<String>,
+    pub commit_message: Option<String>,
+    pub reason: Option<String>,
+    pub depth: Option<usize>,
+    pub section: Option<String>,
+    #[serde(default)]
+    pub subtasks: Vec<String>,
+    #[serde(default)]
+    pub iterations_hint: Option<usize>,
 }
 
-#[derive(Debug, Deserialize)]
-struct LoopDecision {
-    action: LoopAction,
-    target_item: Option<String>,
-    worker_prompt: Option<String>,
-    commit_message: Option<String>,
-    reason: Option<String>,
+#[derive(Debug)]
+struct PinnedItem {
+    decision: LoopDecision,
+    remaining: usize,
+}
+
+impl LoopDecision {
+    pub fn has_target(&self) -> bool {
+        self.target_item
+            .as_deref()
+            .is_some_and(|item| !item.trim().is_empty())
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
-enum LoopAction {
+pub enum LoopAction {
     Delegate,
+    Split,
     Done,
 }
 
@@ -50,40 +216,419 @@ impl LoopRunner {
         Self {
             config,
             config_path,
+            project_root: None,
+            loop_agent: None,
+            worker_agent: None,
+            reviewer_agent: None,
+            fs: Arc::new(RealFs),
         }
     }
 
+    pub fn with_project_root(mut self, project_root: PathBuf) -> Self {
+        self.project_root = Some(project_root);
+        self
+    }
+
+    pub fn with_loop_agent(mut self, agent: Arc<dyn Agent>) -> Self {
+        self.loop_agent = Some(agent);
+        self
+    }
+
+    pub fn with_worker_agent(mut self, agent: Arc<dyn Agent>) -> Self {
+        self.worker_agent = Some(agent);
+        self
+    }
+
+    pub fn with_reviewer_agent(mut self, agent: Arc<dyn Agent>) -> Self {
+        self.reviewer_agent = Some(agent);
+        self
+    }
+
+    pub fn with_fs(mut self, fs: Arc<dyn FileSystem>) -> Self {
+        self.fs = fs;
+        self
+    }
+
     pub fn run(&self, options: &RunOptions) -> Result<RunSummary> {
-        let root = self.project_root();
-        let prd_path = root.join(&self.config.prd.file);
-        let loop_agent = CliAgent::new(self.config.loop_agent.clone());
-        let worker_agent = CliAgent::new(self.config.worker_agent.clone());
+        let started = Instant::now();
+        let state_root = self.project_root();
+        let main_repo = self.repo_root();
+        self.config
+            .validate_paths(&state_root, &main_repo, self.fs.as_ref())
+            .map_err(LaunError::invalid_config)?;
+        if options.interactive {
+            interactive::ensure_terminal("--interactive")?;
+        }
+        if options.step {
+            interactive::ensure_terminal("--step")?;
+        }
+        let use_worktree = self.config.workflow.use_worktree && !options.stubs_worker();
+        let git_error = Git::new(&main_repo).ensure_available().err();
+        if use_worktree && let Some(err) = git_error {
+            bail!(LaunError::git("workflow.use_worktree requires git").with_source(err));
+        }
+        let prd_path = state_root.join(&self.config.prd.file);
+        let mut workflow = self.effective_workflow(&prd_path)?;
+        let prd_before = self
+            .fs
+            .read_to_string(&prd_path)
+            .with_context(|| format!("failed to read PRD file {}", prd_path.display()))?;
+        let git_missing = git_error.is_some();
+        if let Some(err) = git_error {
+            if workflow.commits_enabled() {
+                if workflow.missing_git == MissingGitAction::Abort {
+                    return Err(err.into());
+                }
+                warn!(
+                    "git is unavailable in {}; running without commits (workflow.missing_git = \"disable_commits\")",
+                    main_repo.display()
+                );
+            } else {
+                info!(
+                    "{} is not a git repository; changed-file tracking is unavailable",
+                    main_repo.display()
+                );
+            }
+            workflow.auto_commit = false;
+        }
+        if !options.dry_run {
+            self.ensure_agent_commands(&main_repo, !options.dry_run_worker)?;
+        }
+        if workflow.all_test_commands().is_empty() {
+            warn!(
+                "No execution_tests or workflow.tests phases are configured; items will be committed and marked done without verification. Set workflow.require_tests to refuse this."
+            );
+        }
         let max_iterations = options
             .max_iterations_override
-            .unwrap_or(self.config.workflow.max_iterations);
+            .unwrap_or(workflow.max_iterations);
+        let patch_mode = self.config.worker_agent.output_mode == OutputMode::Patch;
 
-        let mut summary = RunSummary::default();
-        let mut loop_context = String::new();
+        if workflow.commits_enabled()
+            && !use_worktree
+            && !options.stubs_worker()
+            && !options.allow_dirty
+            && Git::new(&main_repo).has_uncommitted_changes()?
+        {
+            bail!(
+                "the working tree in {} has uncommitted changes that would be mixed into laun's commits. \
+                 Commit or stash them first, or re-run with --allow-dirty.",
+                main_repo.display()
+            );
+        }
+        let item_filter = options.item_filter()?;
+        if item_filter.is_active() {
+            let mut prd = PrdDocument::load_from(self.fs.as_ref(), &prd_path)?;
+            prd.retain_claimable(&item_filter);
+            if prd.unchecked_items().is_empty() {
+                bail!(
+                    "no unchecked PRD items in {} match the given --filter/--tag selection",
+                    prd_path.display()
+                );
+            }
+        }
+
+        let previous_state = if options.resume || options.retry_item {
+            state::load(&state_root)?
+        } else {
+            None
+        };
+        let mut failed_item = previous_state
+            .as_ref()
+            .and_then(|state| state.failed_item.clone());
+        let mut retry = None;
+        if options.retry_item {
+            let Some(item) = failed_item.clone() else {
+                bail!(
+                    "no failed item is recorded in {}; run without --retry-item",
+                    state::state_dir(&state_root).display()
+                );
+            };
+            let prd = PrdDocument::load_from(self.fs.as_ref(), &prd_path)?;
+            match prd.find_item(&item.text) {
+                Some(found) if found.status.is_claimable() => {}
+                Some(found) => bail!(
+                    "the failed item `{}` is no longer open in {} (line {})",
+                    item.text,
+                    prd_path.display(),
+                    found.line
+                ),
+                None => bail!(
+                    "the failed item `{}` no longer exists in {}",
+                    item.text,
+                    prd_path.display()
+                ),
+            }
+            retry = Some(item);
+        }
+        let mut worktree = if use_worktree {
+            Some(prepare_worktree(
+                &state_root,
+                &main_repo,
+                options.verbose_git,
+            )?)
+        } else {
+            None
+        };
+        let (root, repo) = match &worktree {
+            Some(worktree) => (
+                worktree_project_root(&state_root, &main_repo, &worktree.path)?,
+                worktree.path.clone(),
+            ),
+            None => (state_root.clone(), main_repo.clone()),
+        };
+        let mut git = Git::new(&repo)
+            .with_signing(workflow.sign_commits, workflow.signing_key.clone())
+            .with_abort_on_conflict(workflow.abort_on_conflict)
+            .with_verbose(options.verbose_git);
+        if git_missing {
+            git = git.without_repository();
+        }
+        let env_file = state_root.join(workflow.env_file.as_deref().unwrap_or(".env"));
+        let env = dotenv::load(&env_file, workflow.env_override)?;
+        if !env.is_empty() {
+            debug!(
+                "Loaded {} variable(s) from {}",
+                env.len(),
+                env_file.display()
+            );
+        }
+        let kill_grace = Duration::from_secs(workflow.agent_kill_grace_secs);
+        let redactor = Arc::new(Redactor::new(
+            &workflow.redact_patterns,
+            workflow.redact_common_secrets,
+        )?);
+        let loop_agent = self.build_loop_agent(options, &env, &repo, kill_grace, &redactor);
+        let escalation_agent =
+            self.build_escalation_agent(options, &env, &repo, kill_grace, &redactor);
+        let worker_agent = self.worker_agent.clone().unwrap_or_else(|| {
+            Arc::new(
+                CliAgent::new(self.config.worker_agent.clone())
+                    .with_role("worker")
+                    .with_extra_args(options.worker_extra_args.clone())
+                    .with_env(env.clone())
+                    .with_working_dir(repo.clone())
+                    .with_kill_grace(kill_grace)
+                    .with_redactor(Arc::clone(&redactor)),
+            )
+        });
+        let reviewer_agent: Option<Arc<dyn Agent>> = match &self.config.reviewer_agent {
+            _ if !workflow.review_before_commit => None,
+            _ if self.reviewer_agent.is_some() => self.reviewer_agent.clone(),
+            Some(config) => Some(Arc::new(
+                CliAgent::new(config.clone())
+                    .with_role("reviewer")
+                    .with_env(env.clone())
+                    .with_working_dir(repo.clone())
+                    .with_kill_grace(kill_grace)
+                    .with_redactor(Arc::clone(&redactor)),
+            )),
+            None => None,
+        };
+        if workflow.warmup_agents && !options.dry_run {
+            agent::warm_up(loop_agent.as_ref(), "Loop")?;
+            if !options.dry_run_worker {
+                agent::warm_up(worker_agent.as_ref(), "Worker")?;
+            }
+        }
+        let run_id = previous_state
+            .as_ref()
+            .map(|state| state.run_id.clone())
+            .filter(|run_id| !run_id.is_empty())
+            .unwrap_or_else(date::run_id);
+        info!("Run ID: {run_id}");
+        let mut prompt_config = self.config.clone();
+        prompt_config
+            .variables
+            .insert("run_id".to_string(), run_id.clone());
+
+        let mut summary = RunSummary {
+            run_id: run_id.clone(),
+            ..RunSummary::default()
+        };
+        let mut finished_items: Vec<String> = Vec::new();
+        let mut previous_prd: Option<PrdDocument> = None;
+        let mut worked_items: Vec<String> = Vec::new();
+        let mut skipped_items: Vec<String> = Vec::new();
+        let mut last_item_commit: Option<(String, String)> = None;
+        let mut pinned: Option<PinnedItem> = None;
+        let mut loop_context = previous_state
+            .map(|state| state.loop_context)
+            .unwrap_or_default();
+        let ctx = IterationContext {
+            options,
+            workflow: &workflow,
+            config: &prompt_config,
+            root: &root,
+            repo: &repo,
+            env: &env,
+            redactor: &redactor,
+            kill_grace,
+            patch_mode,
+            git: &git,
+            worker_agent: worker_agent.as_ref(),
+            reviewer_agent: reviewer_agent.as_deref(),
+        };
 
         for step in 1..=max_iterations {
-            let prd = PrdDocument::load(&prd_path)?;
-            println!("PRD loaded! Items found: {}", prd.items.len());
+            if !options.stubs_worker() {
+                save_state(
+                    &state_root,
+                    &run_id,
+                    step - 1,
+                    &loop_context,
+                    failed_item.as_ref(),
+                    None,
+                );
+            }
+            if step > 1 && workflow.iteration_delay_ms > 0 && !options.dry_run {
+                debug!(
+                    "Waiting {}ms before the next iteration",
+                    workflow.iteration_delay_ms
+                );
+                signal::sleep(Duration::from_millis(workflow.iteration_delay_ms));
+            }
+            if signal::interrupted() {
+                summary.stop_reason = StopReason::Interrupted;
+                break;
+            }
+            let mut prd = PrdDocument::load_from(self.fs.as_ref(), &prd_path)?;
+            debug!("PRD loaded! Items found: {}", prd.items.len());
+            if let Some(previous) = &previous_prd {
+                let changes = prd.changes_since(previous);
+                if !changes.is_empty() {
+                    info!("PRD changed since the last iteration: {changes}");
+                    if self.config.prd.reprioritize_on_change {
+                        loop_context.push_str(&format!(
+                            "\nThe PRD was edited during the run ({changes}). \
+                             Re-evaluate which remaining item is most urgent before delegating."
+                        ));
+                    }
+                }
+            }
+            previous_prd = Some(prd.clone());
+            for item in &mut prd.items {
+                if item.status.is_claimable() && worked_items.contains(&item.normalized) {
+                    item.status = ItemStatus::Done;
+                }
+            }
+            prd.items.retain(|item| {
+                !item.status.is_claimable() || !skipped_items.contains(&item.normalized)
+            });
+            if prd.items.is_empty() {
+                warn!(
+                    "No checklist items (`- [ ] ...`) found in {}. Nothing to do.",
+                    prd_path.display()
+                );
+                summary.stop_reason = StopReason::NoItems;
+                break;
+            }
+            if item_filter.is_active() {
+                prd.retain_claimable(&item_filter);
+                if prd.unchecked_items().is_empty()
+                    && !PrdDocument::load_from(self.fs.as_ref(), &prd_path)?
+                        .unchecked_items()
+                        .is_empty()
+                {
+                    info!("All selected PRD items are complete. Stopping.");
+                    summary.stop_reason = StopReason::SelectionComplete;
+                    break;
+                }
+            }
+            let mut claimed_elsewhere = 0;
+            if self.config.prd.claim_in_progress {
+                let before = prd.items.len();
+                prd.items
+                    .retain(|item| item.status != ItemStatus::InProgress);
+                claimed_elsewhere = before - prd.items.len();
+            }
             let unchecked = prd.unchecked_items();
+            let open_items = unchecked.len();
+            if unchecked.is_empty() && claimed_elsewhere > 0 {
+                info!("Only items claimed as in progress elsewhere are left. Stopping.");
+                summary.stop_reason = StopReason::OnlyClaimedItemsLeft;
+                break;
+            }
+            if unchecked.is_empty() && !skipped_items.is_empty() {
+                info!("Only items the worker skipped are left. Stopping.");
+                summary.stop_reason = StopReason::OnlySkippedItemsLeft;
+                break;
+            }
+            if unchecked.is_empty() && !worked_items.is_empty() {
+                info!("Every open PRD item was worked without marking (--no-mark). Stopping.");
+                summary.stop_reason = StopReason::AllItemsWorked;
+                break;
+            }
             if unchecked.is_empty() {
-                println!("PRD is complete. Stopping.");
+                info!("PRD is complete. Stopping.");
+                summary.stop_reason = StopReason::Completed;
                 break;
             }
 
-            println!("\n=== Iteration {step}/{max_iterations} ===");
+            info!("\n=== Iteration {step}/{max_iterations} ===");
             let decision_prompt = build_loop_prompt(
-                &self.config,
+                &prompt_config,
+                &root,
                 &prd_path,
                 &prd,
                 &loop_context,
-                self.config.workflow.execution_tests.as_slice(),
-            );
-            let decision = if options.dry_run {
-                println!(
+                &workflow.all_test_commands(),
+                &git.recent_commits(workflow.include_git_log)?,
+            )?;
+            debug!("Loop prompt:\n{decision_prompt}");
+            let mut retry_failure = None;
+            let mut decision = if let Some(item) = retry.take() {
+                info!(
+                    "Retrying the failed item from the previous run: {}",
+                    item.text
+                );
+                retry_failure = Some(item.failure_report);
+                LoopDecision {
+                    action: LoopAction::Delegate,
+                    target_item: Some(item.text),
+                    worker_prompt: Some(item.worker_task),
+                    commit_message: None,
+                    reason: Some("--retry-item".to_string()),
+                    depth: item.depth,
+                    section: item.section,
+                    subtasks: Vec::new(),
+                    iterations_hint: None,
+                }
+            } else if let Some(pin) = pinned.take().filter(|pin| {
+                pin.decision
+                    .target_item
+                    .as_deref()
+                    .and_then(|target| {
+                        prd.find_item_with(
+                            target,
+                            &MatchOptions {
+                                case_sensitive: self.config.prd.case_sensitive_match,
+                                depth: pin.decision.depth,
+                                section: pin.decision.section.clone(),
+                            },
+                        )
+                    })
+                    .is_some_and(|item| item.status.is_claimable())
+            }) {
+                let target = pin.decision.target_item.clone().unwrap_or_default();
+                info!(
+                    "Staying on pinned item `{target}` without asking the loop agent ({} pinned iteration(s) left)",
+                    pin.remaining - 1
+                );
+                summary.pinned_iterations += 1;
+                retry_failure = failed_item
+                    .as_ref()
+                    .filter(|item| item.text == target)
+                    .map(|item| item.failure_report.clone());
+                if pin.remaining > 1 {
+                    pinned = Some(PinnedItem {
+                        decision: pin.decision.clone(),
+                        remaining: pin.remaining - 1,
+                    });
+                }
+                pin.decision
+            } else if options.dry_run {
+                info!(
                     "[dry-run] loop prompt preview: {}",
                     truncate(&decision_prompt, 240)
                 );
@@ -93,361 +638,2627 @@ impl LoopRunner {
                     worker_prompt: Some(format!("Implement PRD item: {}", unchecked[0].text)),
                     commit_message: None,
                     reason: Some("dry-run synthetic decision".to_string()),
+                    depth: Some(unchecked[0].depth),
+                    section: unchecked[0].section.clone(),
+                    subtasks: Vec::new(),
+                    iterations_hint: None,
                 }
             } else {
-                let loop_result = loop_agent.invoke(&decision_prompt)?;
-                parse_loop_decision(&loop_result.stdout)
+                let loop_result = match invoke_agent(
+                    loop_agent.as_ref(),
+                    &self.config.loop_agent,
+                    &decision_prompt,
+                    options.continue_on_agent_error,
+                )? {
+                    AgentOutcome::Output(result) => result,
+                    AgentOutcome::Interrupted => {
+                        warn!("Loop agent interrupted. Stopping.");
+                        summary.stop_reason = StopReason::Interrupted;
+                        summary.iterations = step;
+                        break;
+                    }
+                    AgentOutcome::Failed(err) => {
+                        warn!("Loop agent failed; continuing with the next iteration: {err:#}");
+                        summary.failed_iterations += 1;
+                        summary.iterations = step;
+                        loop_context = format!(
+                            "The previous loop agent invocation failed:\n{}",
+                            truncate(&format!("{err:#}"), 1000)
+                        );
+                        continue;
+                    }
+                    AgentOutcome::Skipped(code) => {
+                        warn!("Loop agent exited with code {code}; skipping this iteration.");
+                        summary.iterations = step;
+                        continue;
+                    }
+                };
+                debug!("Loop agent response:\n{}", loop_result.stdout);
+                let (decision, retries) = requery_loop_decision(
+                    loop_agent.as_ref(),
+                    escalation_agent.as_deref(),
+                    self.config.loop_agent.decision_retries,
+                    &decision_prompt,
+                    &loop_result.stdout,
+                );
+                summary.decision_retries += retries;
+                let decision = if decision.action != LoopAction::Done && !decision.has_target() {
+                    summary.empty_targets += 1;
+                    warn!("Loop agent delegated without naming a target_item; asking again.");
+                    reprompt_for_target(loop_agent.as_ref(), &decision_prompt).unwrap_or(decision)
+                } else {
+                    decision
+                };
+                pinned = decision
+                    .iterations_hint
+                    .map(|hint| hint.saturating_sub(1).min(workflow.max_pinned_iterations))
+                    .filter(|&remaining| {
+                        remaining > 0
+                            && decision.action == LoopAction::Delegate
+                            && decision.has_target()
+                    })
+                    .map(|remaining| PinnedItem {
+                        decision: decision.clone(),
+                        remaining,
+                    });
+                decision
             };
 
+            if decision.section.is_none() {
+                decision.section = options.section.clone();
+            }
+            if options.step {
+                let detail = serde_json::to_string_pretty(&decision)?;
+                match interactive::pause("decision", &format!("Loop agent decision:\n{detail}"))? {
+                    Pause::Continue => {}
+                    Pause::Skip => {
+                        info!("Discarding the loop agent decision at operator request.");
+                        pinned = None;
+                        loop_context =
+                            "The operator rejected your previous decision. Decide again."
+                                .to_string();
+                        summary.iterations = step;
+                        continue;
+                    }
+                    Pause::Abort => {
+                        summary.stop_reason = StopReason::Aborted;
+                        summary.iterations = step;
+                        break;
+                    }
+                }
+            }
             match decision.action {
                 LoopAction::Done => {
-                    println!(
-                        "Loop agent decided to stop: {}",
-                        decision.reason.unwrap_or_else(|| "no reason".to_string())
-                    );
+                    let reason = decision.reason.unwrap_or_else(|| "no reason".to_string());
+                    info!("Loop agent decided to stop: {reason}");
+                    summary.stop_reason = StopReason::LoopAgentDone(reason);
                     summary.iterations = step;
                     break;
                 }
+                LoopAction::Split => {
+                    let target_item = decision.target_item.as_deref().unwrap_or_default();
+                    loop_context = match self.split_item(
+                        self.fs.as_ref(),
+                        &workflow,
+                        &prd,
+                        &prd_path,
+                        &decision,
+                    )? {
+                        Ok(()) => {
+                            summary.splits += 1;
+                            previous_prd =
+                                Some(PrdDocument::load_from(self.fs.as_ref(), &prd_path)?);
+                            format!(
+                                "Split item `{target_item}` into {} subtask(s) in the PRD. Pick the next item.",
+                                decision.subtasks.len()
+                            )
+                        }
+                        Err(rejected) => {
+                            warn!("Not splitting `{target_item}`: {rejected}");
+                            format!(
+                                "Your split of item `{target_item}` was rejected: {rejected}. Delegate an item instead."
+                            )
+                        }
+                    };
+                    summary.iterations = step;
+                    continue;
+                }
                 LoopAction::Delegate => {}
             }
 
+            if !decision.has_target() {
+                info!(
+                    "No target_item from the loop agent; using the first open item: {}",
+                    unchecked[0].text
+                );
+            }
             let target_item = decision
                 .target_item
+                .filter(|item| !item.trim().is_empty())
                 .unwrap_or_else(|| unchecked[0].text.clone());
-            let worker_task = decision.worker_prompt.unwrap_or_else(|| {
+            let mut worker_task = decision.worker_prompt.unwrap_or_else(|| {
                 format!(
                     "Implement PRD item: {target_item}. Keep changes scoped and verify with tests."
                 )
             });
+            if options.interactive {
+                match interactive::review(&target_item, &worker_task)? {
+                    Review::Approve(task) => worker_task = task,
+                    Review::Skip => {
+                        info!("Skipping item at operator request: {target_item}");
+                        loop_context = format!(
+                            "The operator skipped item `{target_item}`. Pick a different item."
+                        );
+                        summary.iterations = step;
+                        continue;
+                    }
+                    Review::Abort => {
+                        summary.stop_reason = StopReason::Aborted;
+                        summary.iterations = step;
+                        break;
+                    }
+                }
+            }
+            if options.step && !options.stubs_worker() {
+                match interactive::pause(
+                    "worker",
+                    &format!("Target item: {target_item}\nWorker task:\n{worker_task}"),
+                )? {
+                    Pause::Continue => {}
+                    Pause::Skip => {
+                        info!("Skipping the worker for `{target_item}` at operator request.");
+                        pinned = None;
+                        loop_context = format!(
+                            "The operator skipped the worker for item `{target_item}`. Pick a different item."
+                        );
+                        summary.iterations = step;
+                        continue;
+                    }
+                    Pause::Abort => {
+                        summary.stop_reason = StopReason::Aborted;
+                        summary.iterations = step;
+                        break;
+                    }
+                }
+            }
 
-            let worker_prompt = build_worker_prompt(
-                &self.config,
-                &target_item,
-                &worker_task,
-                None,
-                self.config.workflow.execution_tests.as_slice(),
+            let head_before = git.head_commit();
+            let dirty_before: Vec<String> = if options.stubs_worker() {
+                Vec::new()
+            } else {
+                git.changed_paths()?
+                    .into_iter()
+                    .map(|changed| changed.path)
+                    .collect()
+            };
+            let match_options = MatchOptions {
+                case_sensitive: self.config.prd.case_sensitive_match,
+                depth: decision.depth,
+                section: decision.section.clone(),
+            };
+            let claim = if self.config.prd.claim_in_progress && !options.stubs_worker() {
+                let marker = self.config.prd.in_progress_marker;
+                match claim_item_in(
+                    self.fs.as_ref(),
+                    &prd_path,
+                    &target_item,
+                    &match_options,
+                    marker,
+                )? {
+                    MarkOutcome::Marked => {
+                        debug!("Claimed `{target_item}` as in progress");
+                        Some(ItemClaim {
+                            fs: Arc::clone(&self.fs),
+                            path: prd_path.clone(),
+                            item: target_item.clone(),
+                            options: match_options.clone(),
+                        })
+                    }
+                    _ if PrdDocument::load_from(self.fs.as_ref(), &prd_path)?
+                        .find_item_with(&target_item, &match_options)
+                        .is_some_and(|item| item.status == ItemStatus::InProgress) =>
+                    {
+                        warn!("`{target_item}` is already claimed as in progress; skipping it.");
+                        loop_context = format!(
+                            "Item `{target_item}` is already being worked on elsewhere (marked `[{marker}]` in the PRD). Pick a different item."
+                        );
+                        summary.iterations = step;
+                        continue;
+                    }
+                    _ => {
+                        warn!(
+                            "Could not claim `{target_item}` in the PRD; working on it without a claim."
+                        );
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            let test_commands = workflow.test_commands(open_items <= 1);
+            if open_items <= 1
+                && workflow
+                    .tests
+                    .values()
+                    .any(|phase| phase.run_when == RunWhen::BeforeCompletion)
+            {
+                info!(
+                    "`{target_item}` is the last open item; running before_completion test phases too."
+                );
+            }
+            let resolved_item = prd.find_item_with(&target_item, &match_options);
+            let target_normalized = resolved_item.map(|item| item.normalized.clone());
+            let mut acceptance = resolved_item
+                .map(|item| item.acceptance.clone())
+                .unwrap_or_default();
+            let done_when = resolved_item.and_then(|item| item.done_when.clone());
+            let target_files = resolved_item
+                .map(|item| item.files.clone())
+                .unwrap_or_default();
+            if let Some(check) = &done_when {
+                acceptance.push(format!("`{check}` succeeds (checked by the orchestrator)"));
+            }
+            let preamble = with_bootstrap(
+                &workflow,
+                &worker_preamble(&workflow, &root, &repo, &env, &redactor, kill_grace)?,
+                step == 1,
             );
-            if options.dry_run {
-                println!("[dry-run] worker prompt for item: {target_item}");
+            let item = ItemAttempt {
+                step,
+                target: WorkerTarget {
+                    item: &target_item,
+                    task: &worker_task,
+                    acceptance: &acceptance,
+                    files: &target_files,
+                },
+                normalized: target_normalized.as_deref(),
+                dirty_before: &dirty_before,
+                preamble: &preamble,
+                test_commands: &test_commands,
+                depth: decision.depth,
+                section: decision.section.as_deref(),
+            };
+            let worker_prompt = ctx.worker_prompt(&item, retry_failure.as_deref())?;
+            debug!("Worker prompt:\n{worker_prompt}");
+            let mut worker_output = String::new();
+            if options.stubs_worker() {
+                info!("[dry-run] worker prompt for item: {target_item}");
             } else {
-                let worker_result = worker_agent.invoke(&worker_prompt)?;
-                println!(
+                let outcome = ctx.invoke_worker(&worker_prompt)?;
+                let worker_result = match ctx.handle_agent_outcome(
+                    &item,
+                    ItemAgent::Worker,
+                    outcome,
+                    &mut summary,
+                    &mut loop_context,
+                    &mut skipped_items,
+                )? {
+                    Flow::Proceed(result) => result,
+                    Flow::NextIteration => continue,
+                    Flow::Stop => break,
+                };
+                debug!(
                     "Worker response (truncated): {}",
                     truncate(&worker_result.stdout, 240)
                 );
+                trace!("Worker response:\n{}", worker_result.stdout);
+                worker_output = worker_result.stdout;
             }
 
-            let mut test_run = run_test_suite(
-                self.config.workflow.execution_tests.as_slice(),
-                options.dry_run,
-            )?;
-
-            if !test_run.success && !options.dry_run {
-                for attempt in 1..=self.config.workflow.max_fix_attempts {
-                    println!("Tests failed. Running fix attempt {attempt}.");
-                    let fix_prompt = build_worker_prompt(
-                        &self.config,
-                        &target_item,
-                        &worker_task,
-                        Some(&test_run.output),
-                        self.config.workflow.execution_tests.as_slice(),
-                    );
-                    let _ = worker_agent.invoke(&fix_prompt)?;
-                    test_run = run_test_suite(
-                        self.config.workflow.execution_tests.as_slice(),
-                        options.dry_run,
-                    )?;
-                    if test_run.success {
+            let patch_failure = (patch_mode && !options.stubs_worker())
+                .then(|| apply_worker_patch(&git, &worker_output))
+                .flatten();
+            let skip_tests = patch_failure.is_none()
+                && options.step
+                && !options.stubs_worker()
+                && !test_commands.is_empty()
+                && match interactive::pause("tests", &format_test_commands(&test_commands))? {
+                    Pause::Continue => false,
+                    Pause::Skip => {
+                        info!("Skipping the tests for `{target_item}` at operator request.");
+                        true
+                    }
+                    Pause::Abort => {
+                        summary.stop_reason = StopReason::Aborted;
+                        summary.iterations = step;
                         break;
                     }
+                };
+            let mut test_run = match patch_failure {
+                Some(failure) => failure,
+                None if skip_tests => TestRun::default(),
+                None => ctx.run_tests(&test_commands)?,
+            };
+            trace!("Test output:\n{}", test_run.output());
+            if ctx.stop_on_interrupt(&item, &test_run, &mut summary) {
+                break;
+            }
+
+            if !test_run.success() && !options.stubs_worker() {
+                match ctx.run_fix_attempts(
+                    &item,
+                    &mut test_run,
+                    &mut worker_output,
+                    &mut summary,
+                    &mut loop_context,
+                    &mut skipped_items,
+                )? {
+                    Flow::Proceed(()) => {}
+                    Flow::NextIteration => continue,
+                    Flow::Stop => break,
                 }
             }
 
-            if !test_run.success {
-                println!("Tests are still failing. Handing context back to loop agent.");
+            if !test_run.success() {
+                if !options.stubs_worker() {
+                    failed_item = Some(item.fail_item(test_run.failure_report()));
+                    save_state(
+                        &state_root,
+                        &run_id,
+                        step,
+                        &loop_context,
+                        failed_item.as_ref(),
+                        None,
+                    );
+                }
+                if options.fail_fast {
+                    let message = format!(
+                        "tests still failing for `{}` after {} fix attempt(s); aborting (--fail-fast)\n{}",
+                        target_item,
+                        workflow.max_fix_attempts,
+                        test_run.failure_report()
+                    );
+                    if test_run
+                        .failed_command()
+                        .is_some_and(|failed| failed.timed_out.is_some())
+                    {
+                        bail!(LaunError::timeout(message));
+                    }
+                    bail!(message);
+                }
+                warn!("Tests are still failing. Handing context back to loop agent.");
                 loop_context = format!(
                     "Previous attempt failed for item `{}`.\nTest output:\n{}",
-                    target_item, test_run.output
+                    target_item,
+                    test_run.failure_report()
                 );
+                if workflow.summarize_context
+                    && let Some(worker_summary) =
+                        summarize_worker_output(loop_agent.as_ref(), &target_item, &worker_output)
+                {
+                    loop_context.push_str(&format!("\nWorker summary:\n{worker_summary}"));
+                }
                 summary.iterations = step;
                 continue;
             }
 
+            if let Some(allowed) = &workflow.allowed_paths
+                && !options.stubs_worker()
+            {
+                let laun_files = [
+                    prd_path.clone(),
+                    state_root.join(&self.config.prd.archive_file),
+                ];
+                let out_of_scope: Vec<ChangedPath> = git
+                    .changed_paths()?
+                    .into_iter()
+                    .filter(|changed| {
+                        !allowed
+                            .iter()
+                            .any(|pattern| path_matches(pattern, &changed.path))
+                            && !laun_files
+                                .iter()
+                                .any(|file| config::same_path(&repo.join(&changed.path), file))
+                    })
+                    .collect();
+                if !out_of_scope.is_empty() {
+                    let listed = out_of_scope
+                        .iter()
+                        .map(|changed| format!("  - {}", changed.path))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    if workflow.out_of_scope_action == OutOfScopeAction::Abort {
+                        bail!(
+                            "the worker changed files outside workflow.allowed_paths while working on `{target_item}`:\n{listed}\n\
+                             Nothing was committed or marked done; review the changes, or set \
+                             `workflow.out_of_scope_action = \"revert\"` to discard them automatically."
+                        );
+                    }
+                    warn!("Reverting changes outside workflow.allowed_paths:\n{listed}");
+                    git.revert_paths(&out_of_scope)?;
+                    test_run = ctx.run_tests(&test_commands)?;
+                    trace!("Test output:\n{}", test_run.output());
+                    if ctx.stop_on_interrupt(&item, &test_run, &mut summary) {
+                        break;
+                    }
+                    if !test_run.success() {
+                        warn!(
+                            "Tests fail after reverting out-of-scope changes. Handing context back to loop agent."
+                        );
+                        failed_item = Some(item.fail_item(test_run.failure_report()));
+                        loop_context = format!(
+                            "Previous attempt for item `{target_item}` changed files outside the allowed paths ({}). \
+                             Those changes were reverted and the tests then failed:\n{}",
+                            allowed.join(", "),
+                            test_run.failure_report()
+                        );
+                        summary.iterations = step;
+                        continue;
+                    }
+                }
+            }
+
+            if let Some(check) = &done_when
+                && !options.stubs_worker()
+            {
+                let check_run = ctx.run_tests(std::slice::from_ref(check))?;
+                trace!("done_when output:\n{}", check_run.output());
+                if ctx.stop_on_interrupt(&item, &check_run, &mut summary) {
+                    break;
+                }
+                if !check_run.success() {
+                    warn!(
+                        "`{target_item}` is not done: its done_when check `{check}` failed. Handing context back to loop agent."
+                    );
+                    failed_item = Some(item.fail_item(check_run.failure_report()));
+                    loop_context = format!(
+                        "Item `{target_item}` is not done yet: its done_when check `{check}` failed, so nothing was committed or marked.\n{}",
+                        check_run.failure_report()
+                    );
+                    summary.iterations = step;
+                    continue;
+                }
+                info!("done_when check `{check}` passed for `{target_item}`.");
+            }
+
+            if !options.stubs_worker() {
+                let rejection = match ctx.review_gate(
+                    &item,
+                    &mut summary,
+                    &mut loop_context,
+                    &mut skipped_items,
+                )? {
+                    Flow::Proceed(rejection) => rejection,
+                    Flow::NextIteration => continue,
+                    Flow::Stop => break,
+                };
+                if let Some(report) = rejection {
+                    warn!(
+                        "`{target_item}` did not pass code review. Handing context back to loop agent."
+                    );
+                    failed_item = Some(item.fail_item(report.clone()));
+                    loop_context = format!(
+                        "Item `{target_item}` did not pass code review, so nothing was committed or marked.\n{report}"
+                    );
+                    summary.iterations = step;
+                    continue;
+                }
+            }
+
+            drop(claim);
+            let removed_mid_run = match &target_normalized {
+                Some(normalized) if !options.stubs_worker() => {
+                    !PrdDocument::load_from(self.fs.as_ref(), &prd_path)?
+                        .items
+                        .iter()
+                        .any(|item| &item.normalized == normalized)
+                }
+                _ => false,
+            };
+            if removed_mid_run {
+                warn!(
+                    "`{target_item}` was removed from the PRD while the worker was running; keeping its changes without marking anything done."
+                );
+            }
+
             let mut commit_hash = None;
-            if self.config.workflow.auto_commit && !options.dry_run && has_uncommitted_changes()? {
-                let msg = decision
-                    .commit_message
-                    .unwrap_or_else(|| format!("feat: complete PRD item: {target_item}"));
-                commit_hash = Some(commit_all(&msg)?);
-                summary.commits += 1;
-            }
-
-            if self.config.prd.auto_mark_completed && !options.dry_run {
-                if mark_item_done(&prd_path, &target_item)? {
-                    println!("Marked PRD item done: {target_item}");
-                    summary.completed_items += 1;
+            let commit_due = workflow.auto_commit
+                && workflow.commit_mode == CommitMode::PerItem
+                && !options.stubs_worker()
+                && git.has_uncommitted_changes()?;
+            let commit_due = commit_due
+                && (!options.step
+                    || match interactive::pause(
+                        "commit",
+                        &format!("Commit the changes for `{target_item}`"),
+                    )? {
+                        Pause::Continue => true,
+                        Pause::Skip => {
+                            info!(
+                                "Skipping the commit for `{target_item}` at operator request; its changes stay uncommitted."
+                            );
+                            false
+                        }
+                        Pause::Abort => {
+                            summary.stop_reason = StopReason::Aborted;
+                            summary.iterations = step;
+                            break;
+                        }
+                    });
+            if commit_due {
+                let msg = decision.commit_message.unwrap_or_else(|| {
+                    format!(
+                        "{}: complete PRD item: {target_item}",
+                        commit_type(&workflow.commit_type_map, &target_item)
+                    )
+                });
+                let trailers = if workflow.commit_trailers {
+                    vec![
+                        ("X-Laun-Model", self.config.worker_agent.model.clone()),
+                        ("X-Laun-Iteration", step.to_string()),
+                        ("X-Laun-Run", run_id.clone()),
+                    ]
+                } else if workflow.amend_fixes {
+                    vec![("X-Laun-Run", run_id.clone())]
+                } else {
+                    Vec::new()
+                };
+                let amend_target = last_item_commit
+                    .as_ref()
+                    .filter(|(hash, item)| {
+                        workflow.amend_fixes
+                            && item == &target_item
+                            && git.head_commit().as_ref() == Some(hash)
+                            && git.head_trailer("X-Laun-Run").as_ref() == Some(&run_id)
+                    })
+                    .map(|(hash, _)| hash.clone());
+                if let Err(err) = git.check_conflicts() {
+                    let Some(conflict) = err.source_as::<MergeConflict>() else {
+                        return Err(err.into());
+                    };
+                    warn!("Could not commit `{target_item}`: {conflict}");
+                    loop_context = format!(
+                        "The changes for item `{target_item}` could not be committed because of {conflict}. \
+                         Delegate the item again and ask the worker to resolve the conflicts first."
+                    );
+                    summary.iterations = step;
+                    continue;
+                }
+                let hash = if let Some(previous) = amend_target {
+                    let hash = git.amend_all()?;
+                    info!("Amended commit {previous} for `{target_item}` as {hash}");
+                    for report in summary
+                        .items
+                        .iter_mut()
+                        .filter(|report| report.commit.as_ref() == Some(&previous))
+                    {
+                        report.commit = Some(hash.clone());
+                    }
+                    hash
                 } else {
-                    println!("Could not match PRD item to auto-mark done: {target_item}");
+                    let hash = git.commit_all(&msg, &trailers)?;
+                    summary.commits += 1;
+                    hash
+                };
+                last_item_commit = Some((hash.clone(), target_item.clone()));
+                log_commit_stat(&git, &hash);
+                commit_hash = Some(hash);
+            }
+
+            pinned = None;
+            if !options.stubs_worker() {
+                finished_items.push(target_item.clone());
+                let files = if commit_hash.is_some() {
+                    git.files_changed_since(head_before.as_deref())?
+                } else {
+                    git.changed_paths()?
+                        .into_iter()
+                        .map(|changed| changed.path)
+                        .filter(|path| !dirty_before.contains(path))
+                        .collect()
+                };
+                summary.items.push(ItemReport {
+                    item: target_item.clone(),
+                    commit: commit_hash.clone(),
+                    files,
+                });
+            }
+            if failed_item
+                .as_ref()
+                .is_some_and(|item| item.text == target_item)
+            {
+                failed_item = None;
+            }
+            if options.no_mark && !options.stubs_worker() && !removed_mid_run {
+                info!("Leaving PRD item unchecked for review (--no-mark): {target_item}");
+                summary.unmarked_items.push(target_item.clone());
+                worked_items.extend(target_normalized.clone());
+            } else if (self.config.prd.auto_mark_completed || done_when.is_some())
+                && !options.stubs_worker()
+                && !removed_mid_run
+            {
+                match mark_item_done_in(self.fs.as_ref(), &prd_path, &target_item, &match_options)?
+                {
+                    MarkOutcome::Marked => {
+                        info!("Marked PRD item done: {target_item}");
+                        summary.completed_items += 1;
+                    }
+                    MarkOutcome::NotFound => {
+                        warn!("Could not match PRD item to auto-mark done: {target_item}");
+                    }
+                    MarkOutcome::Ambiguous(lines) => warn!(
+                        "Not auto-marking `{target_item}`: it partially matches several PRD items (lines {}); mark the right one by hand",
+                        lines
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
                 }
             }
 
-            loop_context = format!(
-                "Completed item `{}`. Commit: {}",
-                target_item,
-                commit_hash.unwrap_or_else(|| "none".to_string())
-            );
+            loop_context = if removed_mid_run {
+                format!(
+                    "Item `{}` was removed from the PRD while the worker was running. Its changes were kept. Commit: {}",
+                    target_item,
+                    commit_hash.unwrap_or_else(|| "none".to_string())
+                )
+            } else {
+                format!(
+                    "Completed item `{}`. Commit: {}",
+                    target_item,
+                    commit_hash.unwrap_or_else(|| "none".to_string())
+                )
+            };
+            if workflow.summarize_context
+                && let Some(worker_summary) =
+                    summarize_worker_output(loop_agent.as_ref(), &target_item, &worker_output)
+            {
+                loop_context.push_str(&format!("\nWorker summary:\n{worker_summary}"));
+            }
             summary.iterations = step;
         }
 
-        Ok(summary)
-    }
+        if summary.stop_reason == StopReason::IterationLimit
+            && PrdDocument::load_from(self.fs.as_ref(), &prd_path)?
+                .unchecked_items()
+                .is_empty()
+        {
+            summary.stop_reason = StopReason::Completed;
+        }
 
-    fn project_root(&self) -> &Path {
-        self.config_path.parent().unwrap_or_else(|| Path::new("."))
-    }
-}
+        if workflow.auto_commit
+            && workflow.commit_mode == CommitMode::AtEnd
+            && !finished_items.is_empty()
+            && git.has_uncommitted_changes()?
+        {
+            let mut types = finished_items
+                .iter()
+                .map(|item| commit_type(&workflow.commit_type_map, item));
+            let first = types.next().unwrap_or("feat");
+            let commit_type = if types.all(|other| other == first) {
+                first
+            } else {
+                "feat"
+            };
+            let msg = format!(
+                "{commit_type}: complete {} PRD item(s)\n\n{}",
+                finished_items.len(),
+                finished_items
+                    .iter()
+                    .map(|item| format!("- {item}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            let trailers = if workflow.commit_trailers {
+                vec![
+                    ("X-Laun-Model", self.config.worker_agent.model.clone()),
+                    ("X-Laun-Run", run_id.clone()),
+                ]
+            } else {
+                Vec::new()
+            };
+            let hash = git.commit_all(&msg, &trailers)?;
+            info!("Committed {} item(s) as {hash}", finished_items.len());
+            log_commit_stat(&git, &hash);
+            summary.commits += 1;
+            for report in summary.items.iter_mut().filter(|r| r.commit.is_none()) {
+                report.commit = Some(hash.clone());
+            }
+        }
 
-fn parse_loop_decision(raw: &str) -> LoopDecision {
-    if let Ok(parsed) = serde_json::from_str::<LoopDecision>(raw) {
-        return parsed;
-    }
+        summary.duration_secs = started.elapsed().as_secs_f64();
+        let prd_after = self
+            .fs
+            .read_to_string(&prd_path)
+            .with_context(|| format!("failed to read PRD file {}", prd_path.display()))?;
+        let prd_label = &self.config.prd.file;
+        summary.prd_diff = diff::unified(
+            &prd_before,
+            &prd_after,
+            &format!("a/{prd_label}"),
+            &format!("b/{prd_label}"),
+        );
+        if let Some(path) = &options.prd_diff_file {
+            std::fs::write(path, summary.prd_diff.as_deref().unwrap_or_default())
+                .with_context(|| format!("failed to write PRD diff to {}", path.display()))?;
+            debug!("Wrote the PRD diff to {}", path.display());
+        }
+        if !options.stubs_worker() {
+            save_state(
+                &state_root,
+                &run_id,
+                summary.iterations,
+                &loop_context,
+                failed_item.as_ref(),
+                Some(&summary.stop_reason),
+            );
+            match state::save_last_run(&state_root, &summary) {
+                Ok(path) => debug!("Wrote run report to {}", path.display()),
+                Err(err) => warn!("Failed to write the run report: {err:#}"),
+            }
+        }
 
-    if let Some(parsed) =
-        extract_json_object(raw).and_then(|json| serde_json::from_str::<LoopDecision>(&json).ok())
-    {
-        return parsed;
-    }
+        if summary.stop_reason == StopReason::Completed
+            && !options.stubs_worker()
+            && !git_missing
+            && let Some(tag_template) = &workflow.tag_on_complete
+        {
+            let tag = tag_template
+                .replace("{date}", &date::today())
+                .replace("{timestamp}", &date::timestamp());
+            match git.create_annotated_tag(&tag, "laun: PRD completed") {
+                Ok(()) => info!("Tagged completed PRD as {tag}"),
+                Err(err) => warn!("Failed to create tag {tag}: {:#}", anyhow::Error::from(err)),
+            }
+        }
 
-    LoopDecision {
-        action: LoopAction::Delegate,
-        target_item: None,
-        worker_prompt: Some(raw.trim().to_string()),
-        commit_message: None,
-        reason: None,
-    }
-}
+        if let Some(worktree) = &mut worktree {
+            worktree.finished = true;
+            finish_worktree(&git, &main_repo, &worktree.path, options.keep_worktree)?;
+        }
 
-fn extract_json_object(raw: &str) -> Option<String> {
-    let start = raw.find('{')?;
-    let end = raw.rfind('}')?;
-    if end <= start {
-        return None;
+        if summary.stop_reason == StopReason::Completed
+            && !options.stubs_worker()
+            && let Some(command) = &workflow.on_complete_command
+        {
+            run_on_complete(command, &main_repo, &env, &summary, &redactor, kill_grace);
+        }
+
+        Ok(summary)
     }
-    Some(raw[start..=end].to_string())
-}
 
-fn build_loop_prompt(
-    cfg: &AppConfig,
-    prd_path: &Path,
-    prd: &PrdDocument,
-    loop_context: &str,
-    execution_tests: &[String],
-) -> String {
-    let remaining = prd
-        .unchecked_items()
-        .into_iter()
-        .map(|i| format!("- {}", i.text))
-        .collect::<Vec<_>>()
-        .join("\n");
-    let completed = prd
-        .items
-        .iter()
-        .filter(|i| i.checked)
-        .map(|i| format!("- {}", i.text))
-        .collect::<Vec<_>>()
-        .join("\n");
+    pub fn plan(&self, options: &RunOptions) -> Result<Vec<PlanStep>> {
+        let root = self.project_root();
+        let repo = self.repo_root();
+        self.config
+            .validate_paths(&root, &repo, self.fs.as_ref())
+            .map_err(LaunError::invalid_config)?;
+        let prd_path = root.join(&self.config.prd.file);
+        let workflow = self.effective_workflow(&prd_path)?;
+        self.ensure_agent_commands(&repo, false)?;
+        let env_file = root.join(workflow.env_file.as_deref().unwrap_or(".env"));
+        let env = dotenv::load(&env_file, workflow.env_override)?;
+        let kill_grace = Duration::from_secs(workflow.agent_kill_grace_secs);
+        let redactor = Arc::new(Redactor::new(
+            &workflow.redact_patterns,
+            workflow.redact_common_secrets,
+        )?);
+        let loop_agent = self.build_loop_agent(options, &env, &repo, kill_grace, &redactor);
+        let escalation_agent =
+            self.build_escalation_agent(options, &env, &repo, kill_grace, &redactor);
+        if workflow.warmup_agents {
+            agent::warm_up(loop_agent.as_ref(), "Loop")?;
+        }
+        let max_iterations = options
+            .max_iterations_override
+            .unwrap_or(workflow.max_iterations);
+        let item_filter = options.item_filter()?;
+        let mut prompt_config = self.config.clone();
+        prompt_config
+            .variables
+            .insert("run_id".to_string(), "plan".to_string());
 
-    format!(
-        r#"{system}
-
-Role: Loop manager (fast model). Decide the next task for the implementation agent.
-PRD file: {prd_file}
-
-Visible files for you:
-{loop_files}
-
-Visible tests for you:
-{loop_tests}
-
-Execution tests run by orchestrator:
-{exec_tests}
-
-Completed PRD items:
-{completed}
-
-Remaining PRD items:
-{remaining}
-
-Prior orchestration context:
-{context}
-
-Respond with JSON only:
-{{
-  "action": "delegate" | "done",
-  "target_item": "exact PRD item text to execute",
-  "worker_prompt": "concrete implementation instructions",
-  "commit_message": "optional commit message",
-  "reason": "optional short rationale"
-}}
-"#,
-        system = cfg.loop_agent.system_prompt,
-        prd_file = prd_path.display(),
-        loop_files = format_lines(cfg.loop_agent.visible_files.as_slice()),
-        loop_tests = format_lines(cfg.loop_agent.visible_tests.as_slice()),
-        exec_tests = format_lines(execution_tests),
-        completed = if completed.is_empty() {
-            "(none)".to_string()
-        } else {
-            completed
-        },
-        remaining = remaining,
-        context = if loop_context.is_empty() {
-            "(none)".to_string()
-        } else {
-            loop_context.to_string()
+        let git = Git::new(&repo);
+        let draft = MemoryFs::new().with_file(
+            &prd_path,
+            self.fs
+                .read_to_string(&prd_path)
+                .with_context(|| format!("failed to read PRD file {}", prd_path.display()))?,
+        );
+        let mut steps = Vec::new();
+        let mut loop_context = String::new();
+        for step in 1..=max_iterations {
+            if signal::interrupted() {
+                warn!("Interrupted; the plan below is incomplete.");
+                break;
+            }
+            let mut prd = PrdDocument::load_from(&draft, &prd_path)?;
+            prd.retain_claimable(&item_filter);
+            let unchecked = prd.unchecked_items();
+            let Some(first) = unchecked.first() else {
+                info!("Every selected PRD item is planned.");
+                break;
+            };
+            let fallback_item = first.text.clone();
+
+            info!("Planning step {step}/{max_iterations}");
+            let decision_prompt = build_loop_prompt(
+                &prompt_config,
+                &root,
+                &prd_path,
+                &prd,
+                &loop_context,
+                &workflow.all_test_commands(),
+                &git.recent_commits(workflow.include_git_log)?,
+            )?;
+            debug!("Loop prompt:\n{decision_prompt}");
+            let response = match invoke_agent(
+                loop_agent.as_ref(),
+                &self.config.loop_agent,
+                &decision_prompt,
+                false,
+            )? {
+                AgentOutcome::Output(result) => result,
+                AgentOutcome::Interrupted | AgentOutcome::Failed(_) => {
+                    warn!("Interrupted; the plan below is incomplete.");
+                    break;
+                }
+                AgentOutcome::Skipped(code) => {
+                    warn!("Loop agent exited with code {code}; the plan below is incomplete.");
+                    break;
+                }
+            };
+            debug!("Loop agent response:\n{}", response.stdout);
+            let (mut decision, _) = requery_loop_decision(
+                loop_agent.as_ref(),
+                escalation_agent.as_deref(),
+                self.config.loop_agent.decision_retries,
+                &decision_prompt,
+                &response.stdout,
+            );
+            if decision.section.is_none() {
+                decision.section = options.section.clone();
+            }
+            if decision.action == LoopAction::Done {
+                info!(
+                    "Loop agent would stop here: {}",
+                    decision.reason.as_deref().unwrap_or("no reason")
+                );
+                break;
+            }
+            if decision.action == LoopAction::Split {
+                let target_item = decision.target_item.as_deref().unwrap_or_default();
+                loop_context = match self
+                    .split_item(&draft, &workflow, &prd, &prd_path, &decision)?
+                {
+                    Ok(()) => format!(
+                        "Planning only: `{target_item}` was split into {} subtask(s) in the PRD. Pick the next item.",
+                        decision.subtasks.len()
+                    ),
+                    Err(rejected) => {
+                        warn!(
+                            "Loop agent would split `{target_item}`, which is rejected: {rejected}"
+                        );
+                        format!(
+                            "Your split of item `{target_item}` was rejected: {rejected}. Delegate an item instead."
+                        )
+                    }
+                };
+                continue;
+            }
+
+            let item = decision
+                .target_item
+                .filter(|item| !item.trim().is_empty())
+                .unwrap_or(fallback_item);
+            let match_options = MatchOptions {
+                case_sensitive: self.config.prd.case_sensitive_match,
+                depth: decision.depth,
+                section: decision.section,
+            };
+            match mark_item_done_in(&draft, &prd_path, &item, &match_options)? {
+                MarkOutcome::Marked => {}
+                MarkOutcome::NotFound => {
+                    warn!(
+                        "Loop agent picked an item that is already planned or not in the PRD; stopping the plan: {item}"
+                    );
+                    break;
+                }
+                MarkOutcome::Ambiguous(lines) => {
+                    warn!(
+                        "Loop agent picked an item that matches several PRD lines ({lines:?}); stopping the plan: {item}"
+                    );
+                    break;
+                }
+            }
+            steps.push(PlanStep {
+                item: item.clone(),
+                rationale: decision.reason,
+            });
+            loop_context = format!(
+                "Planning only: assume `{item}` has been implemented and committed. Pick the next item."
+            );
+        }
+        Ok(steps)
+    }
+
+    pub fn next_decision(&self, options: &RunOptions) -> Result<LoopDecision> {
+        let root = self.project_root();
+        let repo = self.repo_root();
+        self.config
+            .validate_paths(&root, &repo, self.fs.as_ref())
+            .map_err(LaunError::invalid_config)?;
+        let prd_path = root.join(&self.config.prd.file);
+        let workflow = self.effective_workflow(&prd_path)?;
+        let mut prd = PrdDocument::load_from(self.fs.as_ref(), &prd_path)?;
+        prd.retain_claimable(&options.item_filter()?);
+        if self.config.prd.claim_in_progress {
+            prd.items
+                .retain(|item| item.status != ItemStatus::InProgress);
+        }
+        if prd.unchecked_items().is_empty() {
+            return Ok(LoopDecision {
+                action: LoopAction::Done,
+                target_item: None,
+                worker_prompt: None,
+                commit_message: None,
+                reason: Some("no open PRD items are left".to_string()),
+                depth: None,
+                section: None,
+                subtasks: Vec::new(),
+                iterations_hint: None,
+            });
+        }
+        self.ensure_agent_commands(&repo, false)?;
+        let env_file = root.join(workflow.env_file.as_deref().unwrap_or(".env"));
+        let env = dotenv::load(&env_file, workflow.env_override)?;
+        let kill_grace = Duration::from_secs(workflow.agent_kill_grace_secs);
+        let redactor = Arc::new(Redactor::new(
+            &workflow.redact_patterns,
+            workflow.redact_common_secrets,
+        )?);
+        let loop_agent = self.build_loop_agent(options, &env, &repo, kill_grace, &redactor);
+        let escalation_agent =
+            self.build_escalation_agent(options, &env, &repo, kill_grace, &redactor);
+        let loop_context = state::load(&root)?
+            .map(|state| state.loop_context)
+            .unwrap_or_default();
+        let mut prompt_config = self.config.clone();
+        prompt_config
+            .variables
+            .insert("run_id".to_string(), "next".to_string());
+
+        let git = Git::new(&repo);
+        let decision_prompt = build_loop_prompt(
+            &prompt_config,
+            &root,
+            &prd_path,
+            &prd,
+            &loop_context,
+            &workflow.all_test_commands(),
+            &git.recent_commits(workflow.include_git_log)?,
+        )?;
+        debug!("Loop prompt:\n{decision_prompt}");
+        let response = match invoke_agent(
+            loop_agent.as_ref(),
+            &self.config.loop_agent,
+            &decision_prompt,
+            false,
+        )? {
+            AgentOutcome::Output(result) => result,
+            AgentOutcome::Interrupted => return Err(AgentError::Interrupted.into()),
+            AgentOutcome::Failed(err) => return Err(err),
+            AgentOutcome::Skipped(code) => {
+                bail!(
+                    "loop agent exited with code {code}, which loop_agent.exit_codes maps to skip"
+                )
+            }
+        };
+        debug!("Loop agent response:\n{}", response.stdout);
+        let (mut decision, _) = requery_loop_decision(
+            loop_agent.as_ref(),
+            escalation_agent.as_deref(),
+            self.config.loop_agent.decision_retries,
+            &decision_prompt,
+            &response.stdout,
+        );
+        if decision.action != LoopAction::Done && !decision.has_target() {
+            warn!("Loop agent delegated without naming a target_item; asking again.");
+            decision =
+                reprompt_for_target(loop_agent.as_ref(), &decision_prompt).unwrap_or(decision);
+        }
+        if decision.section.is_none() {
+            decision.section = options.section.clone();
+        }
+        Ok(decision)
+    }
+
+    pub fn test(&self, all_phases: bool) -> Result<Vec<TestOutcome>> {
+        let root = self.project_root();
+        let repo = self.repo_root();
+        self.config
+            .validate_paths(&root, &repo, self.fs.as_ref())
+            .map_err(LaunError::invalid_config)?;
+        let prd_path = root.join(&self.config.prd.file);
+        let workflow = self.effective_workflow(&prd_path)?;
+        let env_file = root.join(workflow.env_file.as_deref().unwrap_or(".env"));
+        let env = dotenv::load(&env_file, workflow.env_override)?;
+        let redactor = Redactor::new(&workflow.redact_patterns, workflow.redact_common_secrets)?;
+        let test_run = run_test_suite(
+            &workflow.test_commands(all_phases),
+            false,
+            &repo,
+            &env,
+            &redactor,
+            workflow.test_timeout_secs,
+            Duration::from_secs(workflow.agent_kill_grace_secs),
+        )?;
+        Ok(test_run
+            .results
+            .into_iter()
+            .map(|result| TestOutcome {
+                status: result.exit_description(),
+                command: result.command,
+                success: result.success,
+                timed_out: result.timed_out.is_some(),
+                output: result.output,
+            })
+            .collect())
+    }
+
+    pub fn estimate(&self, options: &RunOptions) -> Result<Estimate> {
+        let root = self.project_root();
+        let repo = self.repo_root();
+        self.config
+            .validate_paths(&root, &repo, self.fs.as_ref())
+            .map_err(LaunError::invalid_config)?;
+        let prd_path = root.join(&self.config.prd.file);
+        let workflow = self.effective_workflow(&prd_path)?;
+        let env_file = root.join(workflow.env_file.as_deref().unwrap_or(".env"));
+        let env = dotenv::load(&env_file, workflow.env_override)?;
+        let kill_grace = Duration::from_secs(workflow.agent_kill_grace_secs);
+        let redactor = Redactor::new(&workflow.redact_patterns, workflow.redact_common_secrets)?;
+        let patch_mode = self.config.worker_agent.output_mode == OutputMode::Patch;
+        let max_iterations = options
+            .max_iterations_override
+            .unwrap_or(workflow.max_iterations);
+        let item_filter = options.item_filter()?;
+        let mut prompt_config = self.config.clone();
+        prompt_config
+            .variables
+            .insert("run_id".to_string(), "estimate".to_string());
+
+        let git = Git::new(&repo);
+        let recent_commits = git.recent_commits(workflow.include_git_log)?;
+        let draft = MemoryFs::new().with_file(
+            &prd_path,
+            self.fs
+                .read_to_string(&prd_path)
+                .with_context(|| format!("failed to read PRD file {}", prd_path.display()))?,
+        );
+        let mut estimate = Estimate {
+            items: Vec::new(),
+            loop_agent: AgentEstimate::new(&self.config.loop_agent),
+            worker_agent: AgentEstimate::new(&self.config.worker_agent),
+        };
+        let shared_preamble =
+            worker_preamble(&workflow, &root, &repo, &env, &redactor, kill_grace)?;
+        let mut loop_context = String::new();
+        for step in 1..=max_iterations {
+            let mut prd = PrdDocument::load_from(&draft, &prd_path)?;
+            prd.retain_claimable(&item_filter);
+            let unchecked = prd.unchecked_items();
+            let Some(item) = unchecked.first() else {
+                break;
+            };
+            let target_item = item.text.clone();
+            let acceptance = item.acceptance.clone();
+            let open_items = unchecked.len();
+
+            let loop_prompt = build_loop_prompt(
+                &prompt_config,
+                &root,
+                &prd_path,
+                &prd,
+                &loop_context,
+                &workflow.all_test_commands(),
+                &recent_commits,
+            )?;
+            let loop_tokens = estimate
+                .loop_agent
+                .add_call(&loop_prompt, estimate::LOOP_RESPONSE_TOKENS);
+
+            let worker_task = format!(
+                "Implement PRD item: {target_item}. Keep changes scoped and verify with tests."
+            );
+            let preamble = with_bootstrap(&workflow, &shared_preamble, step == 1);
+            let worker_prompt = with_output_mode(
+                patch_mode,
+                with_preamble(
+                    &preamble,
+                    build_worker_prompt(
+                        &prompt_config,
+                        &root,
+                        &WorkerTarget {
+                            item: &target_item,
+                            task: &worker_task,
+                            acceptance: &acceptance,
+                            files: &item.files,
+                        },
+                        None,
+                        &workflow.test_commands(open_items <= 1),
+                    )?,
+                ),
+            );
+            let worker_tokens = estimate
+                .worker_agent
+                .add_call(&worker_prompt, estimate::WORKER_RESPONSE_TOKENS);
+            estimate.items.push(ItemEstimate {
+                item: target_item.clone(),
+                loop_tokens,
+                worker_tokens,
+            });
+
+            let match_options = MatchOptions {
+                case_sensitive: self.config.prd.case_sensitive_match,
+                depth: Some(item.depth),
+                section: item.section.clone(),
+            };
+            if mark_item_done_in(&draft, &prd_path, &target_item, &match_options)?
+                != MarkOutcome::Marked
+            {
+                warn!("Could not resolve `{target_item}` in the PRD; the estimate stops here.");
+                break;
+            }
+            loop_context =
+                format!("Item `{target_item}` was implemented and committed. Pick the next item.");
+        }
+        estimate.loop_agent.price(&self.config.loop_agent);
+        estimate.worker_agent.price(&self.config.worker_agent);
+        Ok(estimate)
+    }
+
+    fn split_item(
+        &self,
+        fs: &dyn FileSystem,
+        workflow: &WorkflowConfig,
+        prd: &PrdDocument,
+        prd_path: &Path,
+        decision: &LoopDecision,
+    ) -> Result<std::result::Result<(), String>> {
+        let target_item = decision.target_item.as_deref().unwrap_or_default();
+        if target_item.trim().is_empty() {
+            return Ok(Err("no target_item was named".to_string()));
+        }
+        let subtasks: Vec<String> = decision
+            .subtasks
+            .iter()
+            .map(|task| task.trim().to_string())
+            .filter(|task| !task.is_empty())
+            .collect();
+        if subtasks.is_empty() {
+            return Ok(Err("no subtasks were given".to_string()));
+        }
+        if subtasks.len() > workflow.max_subtasks {
+            return Ok(Err(format!(
+                "{} subtasks exceed workflow.max_subtasks ({})",
+                subtasks.len(),
+                workflow.max_subtasks
+            )));
+        }
+        if subtasks.iter().any(|task| task.contains('\n')) {
+            return Ok(Err("each subtask must be a single line".to_string()));
+        }
+        let depth = decision
+            .depth
+            .or_else(|| prd.find_item(target_item).map(|item| item.depth))
+            .unwrap_or(0);
+        if depth >= workflow.max_split_depth {
+            return Ok(Err(format!(
+                "the item is nested {depth} level(s) deep and workflow.max_split_depth is {}",
+                workflow.max_split_depth
+            )));
+        }
+        let match_options = MatchOptions {
+            case_sensitive: self.config.prd.case_sensitive_match,
+            depth: decision.depth,
+            section: decision.section.clone(),
+        };
+        match split_item_in(fs, prd_path, target_item, &subtasks, &match_options)? {
+            MarkOutcome::Marked => {
+                info!(
+                    "Split PRD item into {} subtask(s): {target_item}",
+                    subtasks.len()
+                );
+                Ok(Ok(()))
+            }
+            MarkOutcome::NotFound => Ok(Err("it is not an open item in the PRD".to_string())),
+            MarkOutcome::Ambiguous(lines) => Ok(Err(format!(
+                "it matches several PRD lines ({})",
+                lines
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
         }
+    }
+
+    fn ensure_agent_commands(&self, repo: &Path, with_worker: bool) -> Result<()> {
+        let mut agents = Vec::new();
+        if self.loop_agent.is_none() {
+            agents.push(("loop_agent", &self.config.loop_agent));
+        }
+        if with_worker && self.worker_agent.is_none() {
+            agents.push(("worker_agent", &self.config.worker_agent));
+        }
+        if with_worker
+            && self.config.workflow.review_before_commit
+            && self.reviewer_agent.is_none()
+            && let Some(reviewer) = &self.config.reviewer_agent
+        {
+            agents.push(("reviewer_agent", reviewer));
+        }
+        agent::ensure_commands_available(
+            &agents,
+            repo,
+            self.config.workflow.missing_agent_exit_code,
+        )
+    }
+
+    fn build_loop_agent(
+        &self,
+        options: &RunOptions,
+        env: &[(String, String)],
+        repo: &Path,
+        kill_grace: Duration,
+        redactor: &Arc<Redactor>,
+    ) -> Arc<dyn Agent> {
+        self.loop_agent.clone().unwrap_or_else(|| {
+            self.loop_cli_agent(
+                self.config.loop_agent.clone(),
+                options,
+                env,
+                repo,
+                kill_grace,
+                redactor,
+            )
+        })
+    }
+
+    fn build_escalation_agent(
+        &self,
+        options: &RunOptions,
+        env: &[(String, String)],
+        repo: &Path,
+        kill_grace: Duration,
+        redactor: &Arc<Redactor>,
+    ) -> Option<Arc<dyn Agent>> {
+        if self.loop_agent.is_some() {
+            return None;
+        }
+        let model = self.config.loop_agent.escalation_model.clone()?;
+        let config = AgentConfig {
+            model,
+            ..self.config.loop_agent.clone()
+        };
+        Some(self.loop_cli_agent(config, options, env, repo, kill_grace, redactor))
+    }
+
+    fn loop_cli_agent(
+        &self,
+        config: AgentConfig,
+        options: &RunOptions,
+        env: &[(String, String)],
+        repo: &Path,
+        kill_grace: Duration,
+        redactor: &Arc<Redactor>,
+    ) -> Arc<dyn Agent> {
+        Arc::new(
+            CliAgent::new(config)
+                .with_role("loop")
+                .with_extra_args(options.loop_extra_args.clone())
+                .with_env(env.to_vec())
+                .with_working_dir(repo.to_path_buf())
+                .with_kill_grace(kill_grace)
+                .with_redactor(Arc::clone(redactor)),
+        )
+    }
+
+    fn effective_workflow(&self, prd_path: &Path) -> Result<WorkflowConfig> {
+        let metadata = PrdDocument::load_from(self.fs.as_ref(), prd_path)?.metadata;
+        let mut workflow = self.config.workflow.clone();
+        if let Some(max_iterations) = metadata.max_iterations {
+            if max_iterations == 0 {
+                bail!("max_iterations in the PRD frontmatter must be > 0");
+            }
+            workflow.max_iterations = max_iterations;
+        }
+        if let Some(max_fix_attempts) = metadata.max_fix_attempts {
+            workflow.max_fix_attempts = max_fix_attempts;
+        }
+        if let Some(execution_tests) = metadata.execution_tests {
+            workflow.execution_tests = execution_tests;
+        }
+        workflow
+            .check_allowed_commands()
+            .with_context(|| format!("invalid frontmatter in {}", prd_path.display()))?;
+        if workflow.require_tests && workflow.all_test_commands().is_empty() {
+            bail!(
+                "workflow.require_tests is set but no execution_tests or workflow.tests phases are configured in the config or in the frontmatter of {}",
+                prd_path.display()
+            );
+        }
+        Ok(workflow)
+    }
+
+    pub fn project_root(&self) -> PathBuf {
+        self.project_root
+            .clone()
+            .unwrap_or_else(|| self.config.project_root(&self.config_path))
+    }
+
+    pub fn prd_path(&self) -> PathBuf {
+        self.project_root().join(&self.config.prd.file)
+    }
+
+    pub fn repo_root(&self) -> PathBuf {
+        self.config
+            .repo_root(&self.config_path, &self.project_root())
+    }
+}
+
+struct Worktree {
+    path: PathBuf,
+    main_repo: PathBuf,
+    created: Option<(String, Option<String>)>,
+    finished: bool,
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let Some((branch, base)) = &self.created else {
+            return;
+        };
+        let git = Git::new(&self.path);
+        if git.has_uncommitted_changes().unwrap_or(true) || git.head_commit() != *base {
+            warn!(
+                "Keeping worktree {} (branch {branch}) after the failed run because it has changes; the next run reuses it",
+                self.path.display()
+            );
+            return;
+        }
+        let main = Git::new(&self.main_repo);
+        match main
+            .remove_worktree(&self.path)
+            .and_then(|()| main.delete_branch(branch))
+        {
+            Ok(()) => info!(
+                "Removed the unused worktree {} and branch {branch}",
+                self.path.display()
+            ),
+            Err(err) => warn!(
+                "Failed to remove worktree {}: {:#}",
+                self.path.display(),
+                anyhow::Error::from(err)
+            ),
+        }
+    }
+}
+
+fn prepare_worktree(state_root: &Path, repo: &Path, verbose: bool) -> Result<Worktree> {
+    let mut worktree = Worktree {
+        path: state::worktree_dir(state_root),
+        main_repo: repo.to_path_buf(),
+        created: None,
+        finished: false,
+    };
+    if worktree.path.join(".git").exists() {
+        info!(
+            "Reusing the worktree at {} from a previous run",
+            worktree.path.display()
+        );
+        return Ok(worktree);
+    }
+    state::ensure_state_dir(state_root)?;
+    let branch = format!("laun/{}", date::run_id());
+    let git = Git::new(repo).with_verbose(verbose);
+    let base = git.head_commit();
+    git.add_worktree(&worktree.path, &branch)
+        .context("failed to create the laun worktree")?;
+    info!(
+        "Created worktree {} on branch {branch}",
+        worktree.path.display()
+    );
+    worktree.created = Some((branch, base));
+    Ok(worktree)
+}
+
+fn worktree_project_root(state_root: &Path, repo: &Path, worktree: &Path) -> Result<PathBuf> {
+    let canonical = |path: &Path| {
+        path.canonicalize()
+            .with_context(|| format!("failed to resolve {}", path.display()))
+    };
+    Ok(
+        match canonical(state_root)?.strip_prefix(canonical(repo)?) {
+            Ok(relative) => worktree.join(relative),
+            Err(_) => state_root.to_path_buf(),
+        },
     )
 }
 
-fn build_worker_prompt(
-    cfg: &AppConfig,
+fn finish_worktree(git: &Git, main_repo: &Path, worktree: &Path, keep: bool) -> Result<()> {
+    let branch = git.current_branch()?;
+    if keep {
+        info!(
+            "Keeping worktree {} (branch {branch}) as requested",
+            worktree.display()
+        );
+        return Ok(());
+    }
+    if git.has_uncommitted_changes()? {
+        warn!(
+            "Keeping worktree {} because it has uncommitted changes; commit or discard them there",
+            worktree.display()
+        );
+        return Ok(());
+    }
+    Git::new(main_repo).remove_worktree(worktree)?;
+    info!(
+        "Removed worktree. Review the run with `git log ..{branch}` and merge it with `git merge {branch}`."
+    );
+    Ok(())
+}
+
+fn save_state(
+    root: &Path,
+    run_id: &str,
+    iteration: usize,
+    loop_context: &str,
+    failed_item: Option<&FailedItem>,
+    stop_reason: Option<&StopReason>,
+) {
+    let run_state = RunState {
+        run_id: run_id.to_string(),
+        iteration,
+        loop_context: loop_context.to_string(),
+        stop_reason: stop_reason.map(ToString::to_string),
+        failed_item: failed_item.cloned(),
+    };
+    if let Err(err) = state::save(root, &run_state) {
+        warn!("Failed to save run state: {err:#}");
+    }
+}
+
+struct ItemClaim {
+    fs: Arc<dyn FileSystem>,
+    path: PathBuf,
+    item: String,
+    options: MatchOptions,
+}
+
+impl Drop for ItemClaim {
+    fn drop(&mut self) {
+        match release_item_in(self.fs.as_ref(), &self.path, &self.item, &self.options) {
+            Ok(MarkOutcome::Marked) => info!("Released the in-progress claim on `{}`", self.item),
+            Ok(_) => {}
+            Err(err) => warn!(
+                "Failed to release the in-progress claim on `{}`: {:#}",
+                self.item,
+                anyhow::Error::from(err)
+            ),
+        }
+    }
+}
+
+enum AgentOutcome {
+    Output(AgentRunResult),
+    Interrupted,
+    Failed(anyhow::Error),
+    Skipped(i32),
+}
+
+enum Flow<T> {
+    Proceed(T),
+    NextIteration,
+    Stop,
+}
+
+#[derive(Clone, Copy)]
+enum ItemAgent {
+    Worker,
+    Fix(usize),
+    Reviewer,
+    ReviewFix(usize),
+}
+
+impl ItemAgent {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Reviewer => "Reviewer agent",
+            _ => "Worker agent",
+        }
+    }
+
+    fn phase(self) -> String {
+        match self {
+            Self::Fix(attempt) => format!(" during fix attempt {attempt}"),
+            Self::ReviewFix(round) => format!(" during review fix attempt {round}"),
+            _ => String::new(),
+        }
+    }
+}
+
+struct IterationContext<'a> {
+    options: &'a RunOptions,
+    workflow: &'a WorkflowConfig,
+    config: &'a AppConfig,
+    root: &'a Path,
+    repo: &'a Path,
+    env: &'a [(String, String)],
+    redactor: &'a Redactor,
+    kill_grace: Duration,
+    patch_mode: bool,
+    git: &'a Git,
+    worker_agent: &'a dyn Agent,
+    reviewer_agent: Option<&'a dyn Agent>,
+}
+
+struct ItemAttempt<'a> {
+    step: usize,
+    target: WorkerTarget<'a>,
+    normalized: Option<&'a str>,
+    dirty_before: &'a [String],
+    preamble: &'a str,
+    test_commands: &'a [String],
+    depth: Option<usize>,
+    section: Option<&'a str>,
+}
+
+impl ItemAttempt<'_> {
+    fn fail_item(&self, failure_report: String) -> FailedItem {
+        FailedItem {
+            text: self.target.item.to_string(),
+            worker_task: self.target.task.to_string(),
+            failure_report,
+            depth: self.depth,
+            section: self.section.map(str::to_string),
+        }
+    }
+}
+
+impl IterationContext<'_> {
+    fn run_tests(&self, commands: &[String]) -> Result<TestRun> {
+        run_test_suite(
+            commands,
+            self.options.stubs_worker(),
+            self.repo,
+            self.env,
+            self.redactor,
+            self.workflow.test_timeout_secs,
+            self.kill_grace,
+        )
+    }
+
+    fn stop_on_interrupt(
+        &self,
+        item: &ItemAttempt,
+        test_run: &TestRun,
+        summary: &mut RunSummary,
+    ) -> bool {
+        if !test_run.interrupted() {
+            return false;
+        }
+        warn!("Tests were interrupted. Stopping after this iteration.");
+        summary.stop_reason = StopReason::Interrupted;
+        summary.iterations = item.step;
+        true
+    }
+
+    fn run_worker_tests(&self, item: &ItemAttempt, output: &str) -> Result<TestRun> {
+        match self
+            .patch_mode
+            .then(|| apply_worker_patch(self.git, output))
+            .flatten()
+        {
+            Some(failure) => Ok(failure),
+            None => self.run_tests(item.test_commands),
+        }
+    }
+
+    fn worker_prompt(&self, item: &ItemAttempt, failure_report: Option<&str>) -> Result<String> {
+        let prompt = build_worker_prompt(
+            self.config,
+            self.root,
+            &item.target,
+            failure_report,
+            item.test_commands,
+        )?;
+        Ok(with_output_mode(
+            self.patch_mode,
+            with_preamble(item.preamble, prompt),
+        ))
+    }
+
+    fn invoke_worker(&self, prompt: &str) -> Result<AgentOutcome> {
+        invoke_agent(
+            self.worker_agent,
+            &self.config.worker_agent,
+            prompt,
+            self.options.continue_on_agent_error,
+        )
+    }
+
+    fn handle_agent_outcome(
+        &self,
+        item: &ItemAttempt,
+        agent: ItemAgent,
+        outcome: AgentOutcome,
+        summary: &mut RunSummary,
+        loop_context: &mut String,
+        skipped_items: &mut Vec<String>,
+    ) -> Result<Flow<AgentRunResult>> {
+        let target_item = item.target.item;
+        match outcome {
+            AgentOutcome::Output(result) => return Ok(Flow::Proceed(result)),
+            AgentOutcome::Interrupted => {
+                warn!(
+                    "{} interrupted. Stopping after this iteration.",
+                    agent.name()
+                );
+                summary.stop_reason = StopReason::Interrupted;
+                summary.iterations = item.step;
+                return Ok(Flow::Stop);
+            }
+            AgentOutcome::Failed(err) => {
+                warn!(
+                    "{} failed{}; continuing with the next iteration: {err:#}",
+                    agent.name(),
+                    agent.phase()
+                );
+                summary.failed_iterations += 1;
+                *loop_context = match agent {
+                    ItemAgent::Reviewer => format!(
+                        "The reviewer agent failed while reviewing item `{target_item}`, so nothing was committed or marked:\n{}",
+                        truncate(&format!("{err:#}"), 1000)
+                    ),
+                    _ => worker_failure_context(target_item, &err),
+                };
+            }
+            AgentOutcome::Skipped(code) => {
+                *loop_context = skip_item(self.git, item.dirty_before, target_item, code)?;
+                skipped_items.extend(item.normalized.map(str::to_string));
+                summary.skipped_items.push(target_item.to_string());
+            }
+        }
+        summary.iterations = item.step;
+        Ok(Flow::NextIteration)
+    }
+
+    fn run_fix_attempts(
+        &self,
+        item: &ItemAttempt,
+        test_run: &mut TestRun,
+        worker_output: &mut String,
+        summary: &mut RunSummary,
+        loop_context: &mut String,
+        skipped_items: &mut Vec<String>,
+    ) -> Result<Flow<()>> {
+        for attempt in 1..=self.workflow.max_fix_attempts {
+            if let Some(failed) = test_run.failed_command() {
+                info!(
+                    "Tests failed: `{}` ({}).",
+                    failed.command,
+                    failed.exit_description()
+                );
+            }
+            info!("Running fix attempt {attempt}.");
+            summary.fix_attempts += 1;
+            let fix_prompt = self.worker_prompt(item, Some(&test_run.failure_report()))?;
+            debug!("Fix prompt:\n{fix_prompt}");
+            let before_fix = self.git.worktree_fingerprint();
+            let outcome = self.invoke_worker(&fix_prompt)?;
+            let fix_result = match self.handle_agent_outcome(
+                item,
+                ItemAgent::Fix(attempt),
+                outcome,
+                summary,
+                loop_context,
+                skipped_items,
+            )? {
+                Flow::Proceed(result) => result,
+                Flow::NextIteration => return Ok(Flow::NextIteration),
+                Flow::Stop => return Ok(Flow::Stop),
+            };
+            trace!("Worker response:\n{}", fix_result.stdout);
+            worker_output.push_str(&format!(
+                "\n\nFix attempt {attempt}:\n{}",
+                fix_result.stdout
+            ));
+            let patch_failure = self
+                .patch_mode
+                .then(|| apply_worker_patch(self.git, &fix_result.stdout))
+                .flatten();
+            let after_fix = self.git.worktree_fingerprint();
+            *test_run = match patch_failure {
+                Some(failure) => failure,
+                None => self.run_tests(item.test_commands)?,
+            };
+            trace!("Test output:\n{}", test_run.output());
+            if self.stop_on_interrupt(item, test_run, summary) {
+                return Ok(Flow::Stop);
+            }
+            if test_run.success() {
+                if before_fix.is_some() && before_fix == after_fix {
+                    warn!(
+                        "Tests passed without any file changes since the failing run. `{}` is possibly flaky.",
+                        item.target.item
+                    );
+                    summary.flaky_items.push(item.target.item.to_string());
+                }
+                break;
+            }
+        }
+        Ok(Flow::Proceed(()))
+    }
+
+    fn review_gate(
+        &self,
+        item: &ItemAttempt,
+        summary: &mut RunSummary,
+        loop_context: &mut String,
+        skipped_items: &mut Vec<String>,
+    ) -> Result<Flow<Option<String>>> {
+        let Some(reviewer) = self.reviewer_agent else {
+            return Ok(Flow::Proceed(None));
+        };
+        let target_item = item.target.item;
+        let reviewer_config = self
+            .config
+            .reviewer_agent
+            .as_ref()
+            .unwrap_or(&self.config.worker_agent);
+        let mut round = 0;
+        loop {
+            let review_prompt = build_review_prompt(
+                self.config,
+                reviewer_config,
+                self.root,
+                &item.target,
+                &self.git.working_diff()?,
+            )?;
+            debug!("Review prompt:\n{review_prompt}");
+            let outcome = invoke_agent(
+                reviewer,
+                reviewer_config,
+                &review_prompt,
+                self.options.continue_on_agent_error,
+            )?;
+            if let AgentOutcome::Skipped(code) = outcome {
+                warn!("Reviewer agent exited with code {code}; continuing without a review.");
+                return Ok(Flow::Proceed(None));
+            }
+            let review = match self.handle_agent_outcome(
+                item,
+                ItemAgent::Reviewer,
+                outcome,
+                summary,
+                loop_context,
+                skipped_items,
+            )? {
+                Flow::Proceed(result) => {
+                    trace!("Reviewer response:\n{}", result.stdout);
+                    parse_review(&result.stdout)
+                }
+                Flow::NextIteration => return Ok(Flow::NextIteration),
+                Flow::Stop => return Ok(Flow::Stop),
+            };
+            if review.verdict == ReviewVerdict::Approve {
+                info!("Reviewer approved the changes for `{target_item}`.");
+                return Ok(Flow::Proceed(None));
+            }
+            summary.review_change_requests += 1;
+            let report = format!(
+                "The code reviewer requested changes:\n{}",
+                review.comments.trim()
+            );
+            if round == self.workflow.max_fix_attempts {
+                return Ok(Flow::Proceed(Some(report)));
+            }
+            round += 1;
+            info!("Reviewer requested changes; running review fix attempt {round}.");
+            summary.fix_attempts += 1;
+            let fix_prompt = self.worker_prompt(item, Some(&report))?;
+            debug!("Review fix prompt:\n{fix_prompt}");
+            let outcome = self.invoke_worker(&fix_prompt)?;
+            let fix_result = match self.handle_agent_outcome(
+                item,
+                ItemAgent::ReviewFix(round),
+                outcome,
+                summary,
+                loop_context,
+                skipped_items,
+            )? {
+                Flow::Proceed(result) => result,
+                Flow::NextIteration => return Ok(Flow::NextIteration),
+                Flow::Stop => return Ok(Flow::Stop),
+            };
+            trace!("Worker response:\n{}", fix_result.stdout);
+            let test_run = self.run_worker_tests(item, &fix_result.stdout)?;
+            trace!("Test output:\n{}", test_run.output());
+            if self.stop_on_interrupt(item, &test_run, summary) {
+                return Ok(Flow::Stop);
+            }
+            if !test_run.success() {
+                return Ok(Flow::Proceed(Some(format!(
+                    "Tests failed after addressing the reviewer's comments:\n{}",
+                    test_run.failure_report()
+                ))));
+            }
+        }
+    }
+}
+
+const EXIT_CODE_RETRIES: usize = 2;
+
+fn invoke_agent(
+    agent: &dyn Agent,
+    config: &AgentConfig,
+    prompt: &str,
+    continue_on_error: bool,
+) -> Result<AgentOutcome> {
+    let mut retries = 0;
+    loop {
+        let err = match agent.invoke(prompt) {
+            Ok(result) => {
+                let code = result.code.unwrap_or_default();
+                match config.exit_code_action(result.code) {
+                    None => return Ok(AgentOutcome::Output(result)),
+                    Some(ExitCodeAction::Skip) => return Ok(AgentOutcome::Skipped(code)),
+                    Some(ExitCodeAction::Retry) if retries < EXIT_CODE_RETRIES => {
+                        retries += 1;
+                        warn!(
+                            "Agent exited with code {code}; retrying ({retries}/{EXIT_CODE_RETRIES})"
+                        );
+                        continue;
+                    }
+                    Some(ExitCodeAction::Retry) => anyhow!(
+                        "agent exited with code {code} after {EXIT_CODE_RETRIES} retries\nstdout:\n{}",
+                        result.stdout
+                    ),
+                    Some(ExitCodeAction::Fail) => anyhow!(
+                        "agent exited with code {code}, which exit_codes maps to `fail`\nstdout:\n{}",
+                        result.stdout
+                    ),
+                }
+            }
+            Err(err) if err.source_as::<AgentError>() == Some(&AgentError::Interrupted) => {
+                return Ok(AgentOutcome::Interrupted);
+            }
+            Err(err) => err.into(),
+        };
+        return if continue_on_error {
+            Ok(AgentOutcome::Failed(err))
+        } else {
+            Err(err)
+        };
+    }
+}
+
+fn summarize_worker_output(
+    agent: &dyn Agent,
     target_item: &str,
-    worker_task: &str,
-    failure_output: Option<&str>,
-    execution_tests: &[String],
-) -> String {
-    let failure_block = failure_output
-        .map(|output| {
-            format!(
-                "Previous test failures to fix first:\n{}\n",
-                truncate(output, 3000)
-            )
-        })
-        .unwrap_or_default();
+    worker_output: &str,
+) -> Option<String> {
+    if worker_output.trim().is_empty() {
+        return None;
+    }
+    let prompt = build_summary_prompt(target_item, worker_output);
+    debug!("Summary prompt:\n{prompt}");
+    match agent.invoke(&prompt) {
+        Ok(result) if !result.stdout.trim().is_empty() => {
+            debug!("Worker summary:\n{}", result.stdout);
+            Some(result.stdout.trim().to_string())
+        }
+        Ok(_) => None,
+        Err(err) => {
+            warn!(
+                "Failed to summarize worker output: {:#}",
+                anyhow::Error::from(err)
+            );
+            None
+        }
+    }
+}
+
+fn run_on_complete(
+    command: &str,
+    root: &Path,
+    env: &[(String, String)],
+    summary: &RunSummary,
+    redactor: &Redactor,
+    kill_grace: Duration,
+) {
+    let mut env = env.to_vec();
+    env.extend([
+        ("LAUN_RUN_ID".to_string(), summary.run_id.clone()),
+        (
+            "LAUN_ITERATIONS".to_string(),
+            summary.iterations.to_string(),
+        ),
+        (
+            "LAUN_COMPLETED_ITEMS".to_string(),
+            summary.completed_items.to_string(),
+        ),
+        ("LAUN_COMMITS".to_string(), summary.commits.to_string()),
+        (
+            "LAUN_SUMMARY".to_string(),
+            serde_json::to_string(summary).unwrap_or_default(),
+        ),
+    ]);
+    info!("Running on_complete_command: {command}");
+    match run_shell(command, root, &env, None, kill_grace) {
+        Ok(result) if result.success => {
+            debug!(
+                "on_complete_command output:\n{}",
+                redactor.apply(&result.output)
+            );
+        }
+        Ok(result) => warn!(
+            "on_complete_command `{command}` failed with exit code {}{}",
+            result
+                .exit_code
+                .map_or_else(|| "none".to_string(), |code| code.to_string()),
+            if result.output.is_empty() {
+                String::new()
+            } else {
+                format!(":\n{}", truncate(&redactor.apply(&result.output), 1000))
+            }
+        ),
+        Err(err) => warn!("Failed to run on_complete_command `{command}`: {err:#}"),
+    }
+}
+
+fn log_commit_stat(git: &Git, hash: &str) {
+    if !log::log_enabled!(log::Level::Trace) {
+        return;
+    }
+    match git.commit_stat(hash, logging::color_enabled()) {
+        Ok(stat) => trace!("Commit {hash}:\n{stat}"),
+        Err(err) => warn!(
+            "Failed to read the diff stat of {hash}: {:#}",
+            anyhow::Error::from(err)
+        ),
+    }
+}
+
+fn skip_item(git: &Git, dirty_before: &[String], target_item: &str, code: i32) -> Result<String> {
+    warn!(
+        "Worker agent exited with code {code}; skipping `{target_item}` and discarding its changes."
+    );
+    let changed: Vec<ChangedPath> = git
+        .changed_paths()?
+        .into_iter()
+        .filter(|changed| !dirty_before.contains(&changed.path))
+        .collect();
+    git.revert_paths(&changed)?;
+    Ok(format!(
+        "The worker skipped item `{target_item}` (exit code {code}) and its changes were discarded. \
+         It will not be offered again in this run; pick a different item."
+    ))
+}
+
+fn worker_failure_context(target_item: &str, err: &anyhow::Error) -> String {
     format!(
-        r#"{system}
-
-Role: Implementation agent (slower, stronger model).
-Current PRD item:
-{target_item}
-
-Task:
-{worker_task}
-
-You may focus on these files:
-{files}
-
-You should internally validate against these tests:
-{tests}
-
-The orchestrator will run this test suite after your turn:
-{exec_tests}
-
-{failure_block}
-Keep output concise. Include:
-1) What changed
-2) What remains risky
-3) Suggested commit message
-"#,
-        system = cfg.worker_agent.system_prompt,
-        target_item = target_item,
-        worker_task = worker_task,
-        files = format_lines(cfg.worker_agent.visible_files.as_slice()),
-        tests = format_lines(cfg.worker_agent.visible_tests.as_slice()),
-        exec_tests = format_lines(execution_tests),
-        failure_block = failure_block,
+        "The worker agent failed while working on item `{target_item}`:\n{}",
+        truncate(&format!("{err:#}"), 1000)
     )
 }
 
+fn reprompt_for_target(agent: &dyn Agent, decision_prompt: &str) -> Option<LoopDecision> {
+    let prompt = format!(
+        "{decision_prompt}\n\nYour previous answer delegated work without naming a `target_item`. \
+         Answer again with the same JSON shape and set `target_item` to the exact text of one remaining PRD item."
+    );
+    match agent.invoke(&prompt) {
+        Ok(result) => {
+            debug!("Loop agent re-prompt response:\n{}", result.stdout);
+            let decision = parse_loop_decision(&result.stdout);
+            (decision.action == LoopAction::Done || decision.has_target()).then_some(decision)
+        }
+        Err(err) => {
+            warn!(
+                "Loop agent re-prompt failed: {:#}",
+                anyhow::Error::from(err)
+            );
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ReviewVerdict {
+    Approve,
+    RequestChanges,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CodeReview {
+    verdict: ReviewVerdict,
+    #[serde(default)]
+    comments: String,
+}
+
+fn parse_review(raw: &str) -> CodeReview {
+    serde_json::from_str::<CodeReview>(raw)
+        .ok()
+        .or_else(|| {
+            extract_json_object(raw).and_then(|json| serde_json::from_str::<CodeReview>(&json).ok())
+        })
+        .unwrap_or_else(|| {
+            warn!("Reviewer answer is not a JSON verdict; treating it as a change request.");
+            CodeReview {
+                verdict: ReviewVerdict::RequestChanges,
+                comments: raw.trim().to_string(),
+            }
+        })
+}
+
+fn requery_loop_decision(
+    agent: &dyn Agent,
+    escalation: Option<&dyn Agent>,
+    max_retries: usize,
+    decision_prompt: &str,
+    response: &str,
+) -> (LoopDecision, usize) {
+    let mut raw = response.to_string();
+    let mut retries = 0;
+    while retries < max_retries {
+        if let Some(decision) = try_parse_loop_decision(&raw) {
+            return (decision, retries);
+        }
+        retries += 1;
+        let agent = match escalation {
+            Some(escalation) => {
+                warn!(
+                    "Loop agent answer is not a JSON decision; asking the escalation model ({retries}/{max_retries})."
+                );
+                escalation
+            }
+            None => {
+                warn!(
+                    "Loop agent answer is not a JSON decision; asking again ({retries}/{max_retries})."
+                );
+                agent
+            }
+        };
+        let prompt = format!(
+            "{decision_prompt}\n\nYour previous answer could not be parsed as the JSON decision object:\n{}\n\n\
+             Answer again with only the JSON object, without any other text.",
+            truncate(raw.trim(), 1000)
+        );
+        match agent.invoke(&prompt) {
+            Ok(result) => {
+                debug!("Loop agent re-query response:\n{}", result.stdout);
+                raw = result.stdout;
+            }
+            Err(err) => {
+                warn!("Loop agent re-query failed: {:#}", anyhow::Error::from(err));
+                break;
+            }
+        }
+    }
+    (parse_loop_decision(&raw), retries)
+}
+
+fn try_parse_loop_decision(raw: &str) -> Option<LoopDecision> {
+    serde_json::from_str::<LoopDecision>(raw).ok().or_else(|| {
+        extract_json_object(raw).and_then(|json| serde_json::from_str::<LoopDecision>(&json).ok())
+    })
+}
+
+pub fn parse_loop_decision(raw: &str) -> LoopDecision {
+    if let Some(parsed) = try_parse_loop_decision(raw) {
+        return parsed;
+    }
+
+    LoopDecision {
+        action: LoopAction::Delegate,
+        target_item: None,
+        worker_prompt: Some(raw.trim().to_string()),
+        commit_message: None,
+        reason: None,
+        depth: None,
+        section: None,
+        subtasks: Vec::new(),
+        iterations_hint: None,
+    }
+}
+
+fn extract_json_object(raw: &str) -> Option<String> {
+    let start = raw.find('{')?;
+    let end = raw.rfind('}')?;
+    if end <= start {
+        return None;
+    }
+    Some(raw[start..=end].to_string())
+}
+
 #[derive(Debug, Clone)]
-struct TestRun {
+struct CommandResult {
+    command: String,
+    exit_code: Option<i32>,
     success: bool,
     output: String,
+    timed_out: Option<u64>,
+    interrupted: bool,
 }
 
-fn run_test_suite(commands: &[String], dry_run: bool) -> Result<TestRun> {
-    if commands.is_empty() {
-        return Ok(TestRun {
-            success: true,
-            output: "No tests configured.".to_string(),
-        });
+impl CommandResult {
+    fn exit_description(&self) -> String {
+        if self.interrupted {
+            return "an interrupt".to_string();
+        }
+        if let Some(secs) = self.timed_out {
+            return format!("a timeout after {secs}s");
+        }
+        match self.exit_code {
+            Some(code) => format!("exit code {code}"),
+            None if self.success => "skipped".to_string(),
+            None => "no exit code (terminated by signal)".to_string(),
+        }
     }
+}
 
-    let mut all_output = String::new();
-    for cmd in commands {
-        if dry_run {
-            all_output.push_str(&format!("[dry-run] {cmd}\n"));
-            continue;
+#[derive(Debug, Clone, Default)]
+struct TestRun {
+    results: Vec<CommandResult>,
+}
+
+impl TestRun {
+    fn success(&self) -> bool {
+        self.results.iter().all(|result| result.success)
+    }
+
+    fn failed_command(&self) -> Option<&CommandResult> {
+        self.results.iter().find(|result| !result.success)
+    }
+
+    fn interrupted(&self) -> bool {
+        self.results.iter().any(|result| result.interrupted)
+    }
+
+    fn output(&self) -> String {
+        if self.results.is_empty() {
+            return "No tests configured.".to_string();
+        }
+        self.results
+            .iter()
+            .map(|result| {
+                format!(
+                    "$ {} ({})\n{}",
+                    result.command,
+                    result.exit_description(),
+                    result.output
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn failure_report(&self) -> String {
+        match self.failed_command() {
+            Some(failed) => format!(
+                "Command `{}` failed with {}.\n{}",
+                failed.command,
+                failed.exit_description(),
+                failed.output
+            ),
+            None => self.output(),
+        }
+    }
+}
+
+fn worker_preamble(
+    workflow: &WorkflowConfig,
+    root: &Path,
+    repo: &Path,
+    env: &[(String, String)],
+    redactor: &Redactor,
+    kill_grace: Duration,
+) -> Result<String> {
+    let mut parts = Vec::new();
+    if let Some(file) = &workflow.prompt_preamble_file {
+        let path = root.join(file);
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read prompt preamble {}", path.display()))?;
+        parts.push(contents.trim().to_string());
+    }
+    if let Some(command) = &workflow.prompt_preamble_command {
+        let result = run_shell(command, repo, env, workflow.test_timeout_secs, kill_grace)?;
+        if result.interrupted {
+            return Err(AgentError::Interrupted.into());
         }
-        let result =
-            run_shell(cmd).with_context(|| format!("failed to run test command: {cmd}"))?;
-        all_output.push_str(&format!("$ {cmd}\n{}\n", result.output));
         if !result.success {
-            return Ok(TestRun {
-                success: false,
-                output: all_output,
-            });
+            bail!(
+                "prompt preamble command `{command}` failed (exit code {:?}):\n{}",
+                result.exit_code,
+                redactor.apply(&result.output)
+            );
         }
+        parts.push(redactor.apply(result.stdout.trim()));
     }
+    Ok(parts
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n"))
+}
 
-    Ok(TestRun {
-        success: true,
-        output: all_output,
-    })
+fn with_bootstrap(workflow: &WorkflowConfig, preamble: &str, first_iteration: bool) -> String {
+    match &workflow.bootstrap_prompt {
+        Some(bootstrap) if first_iteration && !bootstrap.trim().is_empty() => {
+            if preamble.is_empty() {
+                bootstrap.trim().to_string()
+            } else {
+                format!("{}\n\n{preamble}", bootstrap.trim())
+            }
+        }
+        _ => preamble.to_string(),
+    }
 }
 
-fn has_uncommitted_changes() -> Result<bool> {
-    let out = run_shell("git status --porcelain")?;
-    Ok(!out.output.trim().is_empty())
+fn with_output_mode(patch_mode: bool, prompt: String) -> String {
+    if patch_mode {
+        format!("{}\n\n{}\n", prompt.trim_end(), patch::PATCH_INSTRUCTIONS)
+    } else {
+        prompt
+    }
 }
 
-fn commit_all(message: &str) -> Result<String> {
-    run_shell("git add -A")?;
-    run_shell(&format!("git commit -m {}", shell_quote(message)))?;
-    let hash = run_shell("git rev-parse --short HEAD")?;
-    Ok(hash.output.trim().to_string())
+fn apply_worker_patch(git: &Git, output: &str) -> Option<TestRun> {
+    let applied = match patch::extract(output) {
+        Some(patch) => git
+            .apply_patch(&patch)
+            .map_err(|err| format!("{:#}", anyhow::Error::from(err))),
+        None => Err("The worker output contains no unified diff.".to_string()),
+    };
+    match applied {
+        Ok(()) => {
+            info!("Applied the worker's patch.");
+            None
+        }
+        Err(message) => {
+            warn!("Could not apply the worker's patch: {message}");
+            Some(TestRun {
+                results: vec![CommandResult {
+                    command: "git apply".to_string(),
+                    exit_code: Some(1),
+                    success: false,
+                    output: message,
+                    timed_out: None,
+                    interrupted: false,
+                }],
+            })
+        }
+    }
+}
+
+fn with_preamble(preamble: &str, prompt: String) -> String {
+    if preamble.is_empty() {
+        prompt
+    } else {
+        format!("{preamble}\n\n{prompt}")
+    }
+}
+
+fn commit_type<'a>(map: &'a BTreeMap<String, String>, item: &str) -> &'a str {
+    let tag_type = parse_tags(item).into_iter().find_map(|tag| {
+        map.iter()
+            .find(|(key, _)| key.trim_start_matches('#').eq_ignore_ascii_case(&tag))
+            .map(|(_, commit_type)| commit_type.as_str())
+    });
+    tag_type
+        .or_else(|| {
+            item.split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+                .filter(|word| !word.is_empty())
+                .find_map(|word| {
+                    map.iter()
+                        .find(|(key, _)| !key.starts_with('#') && key.eq_ignore_ascii_case(word))
+                        .map(|(_, commit_type)| commit_type.as_str())
+                })
+        })
+        .unwrap_or("feat")
+}
+
+fn format_test_commands(commands: &[String]) -> String {
+    commands
+        .iter()
+        .map(|command| format!("  $ {command}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn run_test_suite(
+    commands: &[String],
+    dry_run: bool,
+    root: &Path,
+    env: &[(String, String)],
+    redactor: &Redactor,
+    timeout_secs: Option<u64>,
+    kill_grace: Duration,
+) -> Result<TestRun> {
+    let mut test_run = TestRun::default();
+    for cmd in commands {
+        if dry_run {
+            test_run.results.push(CommandResult {
+                command: cmd.clone(),
+                exit_code: None,
+                success: true,
+                output: "[dry-run] not executed".to_string(),
+                timed_out: None,
+                interrupted: false,
+            });
+            continue;
+        }
+        let result = run_shell(cmd, root, env, timeout_secs, kill_grace)
+            .with_context(|| format!("failed to run test command: {cmd}"))?;
+        let success = result.success;
+        if result.timed_out {
+            warn!(
+                "Test command `{cmd}` ran longer than {}s and was killed.",
+                timeout_secs.unwrap_or_default()
+            );
+        }
+        test_run.results.push(CommandResult {
+            command: cmd.clone(),
+            exit_code: result.exit_code,
+            success,
+            output: redactor.apply(&result.output),
+            timed_out: timeout_secs.filter(|_| result.timed_out),
+            interrupted: result.interrupted,
+        });
+        if !success {
+            break;
+        }
+    }
+
+    Ok(test_run)
 }
 
 #[derive(Debug)]
 struct ShellRun {
     success: bool,
+    exit_code: Option<i32>,
+    stdout: String,
     output: String,
+    timed_out: bool,
+    interrupted: bool,
 }
 
-fn run_shell(command: &str) -> Result<ShellRun> {
-    let output = Command::new("sh")
-        .arg("-lc")
+fn run_shell(
+    command: &str,
+    root: &Path,
+    env: &[(String, String)],
+    timeout_secs: Option<u64>,
+    kill_grace: Duration,
+) -> Result<ShellRun> {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-lc")
         .arg(command)
-        .output()
+        .current_dir(root)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    process::isolate_process_group(&mut cmd);
+    let mut child = cmd
+        .spawn()
         .with_context(|| format!("failed to spawn shell for `{command}`"))?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    let merged = format!("{}{}", stdout, stderr);
+    let read_all = |mut source: Box<dyn Read + Send>| {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = source.read_to_end(&mut buf);
+            buf
+        })
+    };
+    let stdout = read_all(Box::new(
+        child
+            .stdout
+            .take()
+            .context("command stdout was not captured")?,
+    ));
+    let stderr = read_all(Box::new(
+        child
+            .stderr
+            .take()
+            .context("command stderr was not captured")?,
+    ));
+    let deadline = timeout_secs.map(|secs| Instant::now() + Duration::from_secs(secs));
+    let mut timed_out = false;
+    let mut interrupted = false;
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .with_context(|| format!("failed to wait for `{command}`"))?
+        {
+            break Some(status);
+        }
+        interrupted = signal::interrupted();
+        timed_out = !interrupted && deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if interrupted || timed_out {
+            process::terminate_group(&mut child, kill_grace);
+            break None;
+        }
+        thread::sleep(process::POLL_INTERVAL);
+    };
+    let stdout = String::from_utf8_lossy(&stdout.join().unwrap_or_default()).into_owned();
+    let stderr = stderr.join().unwrap_or_default();
+    let mut output = format!("{}{}", stdout, String::from_utf8_lossy(&stderr))
+        .trim()
+        .to_string();
+    if timed_out {
+        output.push_str(&format!(
+            "\n[laun: killed after {}s without finishing (workflow.test_timeout_secs)]",
+            timeout_secs.unwrap_or_default()
+        ));
+    }
+    if interrupted {
+        output.push_str("\n[laun: killed on interrupt]");
+    }
     Ok(ShellRun {
-        success: output.status.success(),
-        output: merged.trim().to_string(),
+        success: status.is_some_and(|status| status.success()),
+        exit_code: status.and_then(|status| status.code()),
+        stdout,
+        output,
+        timed_out,
+        interrupted,
     })
 }
 
-fn format_lines(lines: &[String]) -> String {
-    if lines.is_empty() {
-        return "(none)".to_string();
+pub(crate) fn truncate(input: &str, max: usize) -> String {
+    match input.char_indices().nth(max) {
+        Some((end, _)) => format!("{}...", &input[..end]),
+        None => input.to_string(),
     }
-    lines
-        .iter()
-        .map(|line| format!("- {line}"))
-        .collect::<Vec<_>>()
-        .join("\n")
 }
 
-fn truncate(input: &str, max: usize) -> String {
-    if input.len() <= max {
-        return input.to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::VecDeque, sync::Mutex};
+
+    #[derive(Debug)]
+    struct ScriptedLoop(Mutex<VecDeque<String>>);
+
+    impl ScriptedLoop {
+        fn delegating(items: &[&str]) -> Self {
+            Self(Mutex::new(
+                items
+                    .iter()
+                    .map(|item| {
+                        serde_json::json!({
+                            "action": "delegate",
+                            "target_item": item,
+                            "worker_prompt": "do it",
+                        })
+                        .to_string()
+                    })
+                    .collect(),
+            ))
+        }
     }
-    format!("{}...", &input[..max])
-}
 
-fn shell_quote(input: &str) -> String {
-    format!("'{}'", input.replace('\'', r"'\''"))
+    impl Agent for ScriptedLoop {
+        fn invoke(&self, _prompt: &str) -> Result<AgentRunResult, LaunError> {
+            let stdout = self
+                .0
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| LaunError::agent("no scripted decision left"))?;
+            Ok(AgentRunResult {
+                stdout,
+                code: Some(0),
+            })
+        }
+    }
+
+    #[derive(Debug)]
+    struct FileWriter {
+        dir: PathBuf,
+        calls: Mutex<usize>,
+    }
+
+    impl Agent for FileWriter {
+        fn invoke(&self, _prompt: &str) -> Result<AgentRunResult, LaunError> {
+            let mut calls = self.calls.lock().unwrap();
+            *calls += 1;
+            let path = self.dir.join(format!("src/item{calls}.txt"));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "done\n").unwrap();
+            Ok(AgentRunResult {
+                stdout: "done".to_string(),
+                code: Some(0),
+            })
+        }
+    }
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    fn run_items(
+        prd: &str,
+        items: &[&str],
+        configure: impl FnOnce(&mut AppConfig),
+    ) -> tempfile::TempDir {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        git(&dir, &["init", "-q"]);
+        git(&dir, &["config", "user.email", "laun@example.com"]);
+        git(&dir, &["config", "user.name", "laun"]);
+        git(&dir, &["config", "commit.gpgsign", "false"]);
+        std::fs::write(dir.join("PRD.md"), prd).unwrap();
+        git(&dir, &["add", "-A"]);
+        git(&dir, &["commit", "-q", "-m", "init"]);
+
+        let mut config = AppConfig::default();
+        config.workflow.execution_tests = vec!["true".to_string()];
+        configure(&mut config);
+        let summary = LoopRunner::new(config, dir.join("laun.toml"))
+            .with_project_root(dir.clone())
+            .with_loop_agent(Arc::new(ScriptedLoop::delegating(items)))
+            .with_worker_agent(Arc::new(FileWriter {
+                dir: dir.clone(),
+                calls: Mutex::new(0),
+            }))
+            .run(&RunOptions::default())
+            .unwrap();
+        assert_eq!(summary.stop_reason, StopReason::Completed);
+        temp
+    }
+
+    #[test]
+    fn commits_never_record_the_in_progress_claim() {
+        let temp = run_items(
+            "- [ ] First\n- [ ] Second\n",
+            &["First", "Second"],
+            |config| {
+                config.prd.claim_in_progress = true;
+            },
+        );
+        let dir = temp.path();
+
+        let commits = git(dir, &["rev-list", "HEAD"]);
+        assert_eq!(commits.lines().count(), 3);
+        for commit in commits.lines() {
+            let prd = git(dir, &["show", &format!("{commit}:PRD.md")]);
+            assert!(!prd.contains("[-]"), "{commit} records a claim:\n{prd}");
+        }
+        assert_eq!(
+            std::fs::read_to_string(dir.join("PRD.md")).unwrap(),
+            "- [x] First\n- [x] Second\n"
+        );
+    }
+
+    #[test]
+    fn allowed_paths_ignore_the_previous_items_done_mark() {
+        let temp = run_items(
+            "- [ ] First\n- [ ] Second\n",
+            &["First", "Second"],
+            |config| {
+                config.workflow.allowed_paths = Some(vec!["src/".to_string()]);
+            },
+        );
+        let dir = temp.path();
+
+        assert_eq!(git(dir, &["rev-list", "HEAD"]).lines().count(), 3);
+        assert!(dir.join("src/item2.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("PRD.md")).unwrap(),
+            "- [x] First\n- [x] Second\n"
+        );
+    }
 }
@@ -0,0 +1,35 @@
+use crate::process::POLL_INTERVAL;
+use anyhow::{Context, Result};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            eprintln!("\nInterrupted again. Exiting immediately.");
+            std::process::exit(130);
+        }
+        eprintln!("\nInterrupt received. Stopping after the current step (Ctrl-C again to force).");
+    })
+    .context("failed to install Ctrl-C handler")
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+pub fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while !interrupted() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        thread::sleep(remaining.min(POLL_INTERVAL));
+    }
+}
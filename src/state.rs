@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const STATE_DIR: &str = ".laun";
+const STATE_FILE: &str = "state.json";
+const WORKTREE_DIR: &str = "worktree";
+const LAST_RUN_FILE: &str = "last-run.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunState {
+    pub run_id: String,
+    pub iteration: usize,
+    #[serde(default)]
+    pub loop_context: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_item: Option<FailedItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedItem {
+    pub text: String,
+    pub worker_task: String,
+    pub failure_report: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+}
+
+pub fn state_dir(root: &Path) -> PathBuf {
+    root.join(STATE_DIR)
+}
+
+pub fn worktree_dir(root: &Path) -> PathBuf {
+    state_dir(root).join(WORKTREE_DIR)
+}
+
+pub fn load(root: &Path) -> Result<Option<RunState>> {
+    let path = state_dir(root).join(STATE_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let raw = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read run state {}", path.display()))?;
+    let state = serde_json::from_str(&raw)
+        .with_context(|| format!("failed to parse run state {}", path.display()))?;
+    Ok(Some(state))
+}
+
+pub fn save(root: &Path, state: &RunState) -> Result<()> {
+    let dir = ensure_state_dir(root)?;
+    let path = dir.join(STATE_FILE);
+    let json = serde_json::to_string_pretty(state)?;
+    fs::write(&path, json).with_context(|| format!("failed to write run state {}", path.display()))
+}
+
+pub fn save_last_run<T: Serialize>(root: &Path, run: &T) -> Result<PathBuf> {
+    let path = ensure_state_dir(root)?.join(LAST_RUN_FILE);
+    let json = serde_json::to_string_pretty(run)?;
+    fs::write(&path, json)
+        .with_context(|| format!("failed to write run report {}", path.display()))?;
+    Ok(path)
+}
+
+pub fn ensure_state_dir(root: &Path) -> Result<PathBuf> {
+    let dir = state_dir(root);
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let ignore = dir.join(".gitignore");
+    if !ignore.exists() {
+        fs::write(&ignore, "*\n")
+            .with_context(|| format!("failed to write {}", ignore.display()))?;
+    }
+    Ok(dir)
+}
@@ -0,0 +1,82 @@
+use anyhow::{Result, bail};
+use std::{collections::BTreeMap, env};
+
+struct Branch {
+    taken: bool,
+    seen_else: bool,
+}
+
+pub fn render(input: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    let mut branches: Vec<Branch> = Vec::new();
+
+    while let Some(start) = rest.find("{{") {
+        let after_open = &rest[start + 2..];
+        let Some(end) = after_open.find("}}") else {
+            break;
+        };
+        let emitting = branches.iter().all(|branch| branch.taken);
+        if emitting {
+            output.push_str(&rest[..start]);
+        }
+        let key = after_open[..end].trim();
+        if let Some(condition) = key.strip_prefix("#if ") {
+            branches.push(Branch {
+                taken: condition_holds(condition.trim(), vars)?,
+                seen_else: false,
+            });
+        } else if key == "else" {
+            let Some(branch) = branches.last_mut() else {
+                bail!("`{{{{else}}}}` without a matching `{{{{#if}}}}`");
+            };
+            if branch.seen_else {
+                bail!("`{{{{#if}}}}` has more than one `{{{{else}}}}`");
+            }
+            branch.taken = !branch.taken;
+            branch.seen_else = true;
+        } else if key == "/if" {
+            if branches.pop().is_none() {
+                bail!("`{{{{/if}}}}` without a matching `{{{{#if}}}}`");
+            }
+        } else {
+            match vars.get(key) {
+                Some(value) if emitting => output.push_str(value),
+                Some(_) => {}
+                None => bail!("unknown template variable `{{{{{key}}}}}`"),
+            }
+        }
+        rest = &after_open[end + 2..];
+    }
+
+    if !branches.is_empty() {
+        bail!("`{{{{#if}}}}` without a closing `{{{{/if}}}}`");
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+fn condition_holds(condition: &str, vars: &BTreeMap<String, String>) -> Result<bool> {
+    let (negated, name) = match condition.strip_prefix('!') {
+        Some(name) => (true, name.trim()),
+        None => (false, condition),
+    };
+    let value = match name.strip_prefix("env.") {
+        Some(var) if !var.is_empty() => env::var(var).ok(),
+        Some(_) => bail!("`{{{{#if {condition}}}}}` needs an environment variable name"),
+        None if vars.contains_key(name) => vars.get(name).cloned(),
+        None => bail!("unknown template variable `{name}` in `{{{{#if {condition}}}}}`"),
+    };
+    Ok(value.is_some_and(|value| !value.is_empty()) != negated)
+}
+
+pub fn render_all(inputs: &[String], vars: &BTreeMap<String, String>) -> Result<Vec<String>> {
+    let mut rendered = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let output = render(input, vars)?;
+        if !output.trim().is_empty() {
+            rendered.push(output);
+        }
+    }
+    Ok(rendered)
+}
@@ -0,0 +1,105 @@
+use crate::signal;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+const POLL_EVERY: Duration = Duration::from_millis(250);
+const SKIPPED_DIRS: &[&str] = &[".git", ".laun", "target", "node_modules"];
+
+type Snapshot = BTreeMap<PathBuf, (Option<SystemTime>, u64)>;
+
+#[derive(Debug)]
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    snapshot: Snapshot,
+}
+
+impl Watcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let snapshot = snapshot(&paths);
+        Self { paths, snapshot }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    pub fn reset(&mut self) {
+        self.snapshot = snapshot(&self.paths);
+    }
+
+    pub fn wait_for_change(&mut self, debounce: Duration) -> Option<Vec<PathBuf>> {
+        let mut changed = Vec::new();
+        let mut last_change = None;
+        loop {
+            if signal::interrupted() {
+                return None;
+            }
+            let current = snapshot(&self.paths);
+            if current != self.snapshot {
+                for path in diff(&self.snapshot, &current) {
+                    if !changed.contains(&path) {
+                        changed.push(path);
+                    }
+                }
+                self.snapshot = current;
+                last_change = Some(Instant::now());
+            }
+            if let Some(at) = last_change
+                && at.elapsed() >= debounce
+            {
+                return Some(changed);
+            }
+            signal::sleep(POLL_EVERY);
+        }
+    }
+}
+
+fn snapshot(paths: &[PathBuf]) -> Snapshot {
+    let mut files = Snapshot::new();
+    for path in paths {
+        collect(path, &mut files);
+    }
+    files
+}
+
+fn collect(path: &Path, files: &mut Snapshot) {
+    let Ok(metadata) = fs::metadata(path) else {
+        return;
+    };
+    if metadata.is_dir() {
+        let Ok(entries) = fs::read_dir(path) else {
+            return;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name();
+            if SKIPPED_DIRS.iter().any(|skipped| name == *skipped) {
+                continue;
+            }
+            collect(&entry.path(), files);
+        }
+    } else {
+        files.insert(
+            path.to_path_buf(),
+            (metadata.modified().ok(), metadata.len()),
+        );
+    }
+}
+
+fn diff(before: &Snapshot, after: &Snapshot) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = after
+        .iter()
+        .filter(|(path, stamp)| before.get(*path) != Some(stamp))
+        .map(|(path, _)| path.clone())
+        .collect();
+    changed.extend(
+        before
+            .keys()
+            .filter(|path| !after.contains_key(*path))
+            .cloned(),
+    );
+    changed
+}
@@ -0,0 +1,65 @@
+use crate::{
+    config::{WebhookOn, WorkflowConfig},
+    runner::RunSummary,
+};
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    status: &'static str,
+    stop_reason: Option<String>,
+    summary: Option<&'a RunSummary>,
+    error: Option<String>,
+}
+
+pub fn notify(workflow: &WorkflowConfig, outcome: &Result<RunSummary>) {
+    let Some(url) = workflow.webhook_url.as_deref() else {
+        return;
+    };
+
+    let payload = match outcome {
+        Ok(summary) => WebhookPayload {
+            status: "completed",
+            stop_reason: Some(summary.stop_reason.to_string()),
+            summary: Some(summary),
+            error: None,
+        },
+        Err(err) => WebhookPayload {
+            status: "failed",
+            stop_reason: None,
+            summary: None,
+            error: Some(format!("{err:#}")),
+        },
+    };
+
+    let wanted = match workflow.webhook_on {
+        WebhookOn::Always => true,
+        WebhookOn::Failure => outcome.is_err(),
+        WebhookOn::Completion => outcome.is_ok(),
+    };
+    if !wanted {
+        return;
+    }
+
+    match post(url, &payload) {
+        Ok(()) => debug!("Delivered {} webhook to {url}", payload.status),
+        Err(err) => warn!("Failed to deliver webhook to {url}: {err:#}"),
+    }
+}
+
+fn post(url: &str, payload: &WebhookPayload) -> Result<()> {
+    let body = serde_json::to_string(payload).context("failed to serialize webhook payload")?;
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(10)))
+        .build()
+        .into();
+    agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(body)
+        .context("webhook request failed")?;
+    Ok(())
+}